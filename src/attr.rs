@@ -0,0 +1,368 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::ffi::{CString, NulError};
+
+use wokwi_chip_ll::{attrInit, attrRead, attrReadFloat, AttrId};
+
+/// Error returned by [`Attribute::try_new`] when the attribute name contains a NUL byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrInitError(NulError);
+
+impl std::fmt::Display for AttrInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid attribute name: {}", self.0)
+    }
+}
+
+impl std::error::Error for AttrInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<NulError> for AttrInitError {
+    fn from(error: NulError) -> Self {
+        AttrInitError(error)
+    }
+}
+
+/// A chip attribute, set in `diagram.json` (e.g. `"attrs": { "frequency": "1000" }`).
+///
+/// The value is read from the simulator on every read, so reads see changes made while the
+/// simulation runs, e.g. from a slider control in the chip's JSON definition.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::attr::Attribute;
+///
+/// let address = Attribute::new("address", 0x42 as f64);
+/// let threshold = Attribute::new("threshold", 2.5);
+/// println!("address=0x{:02x} threshold={}V", address.read_u32(), threshold.read_f64());
+/// ```
+pub struct Attribute {
+    id: AttrId,
+}
+
+impl Attribute {
+    /// Initializes the attribute with the given name. `default_value` is returned by reads when
+    /// the attribute isn't set in the diagram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a NUL byte. Use [`Attribute::try_new`] if the name comes from
+    /// user input.
+    pub fn new(name: &str, default_value: f64) -> Self {
+        Self::try_new(name, default_value).unwrap()
+    }
+
+    /// Initializes the attribute with the given name, failing if the name contains a NUL byte.
+    pub fn try_new(name: &str, default_value: f64) -> Result<Self, AttrInitError> {
+        let c_name = CString::new(name)?;
+        let id = unsafe { attrInit(c_name.as_ptr(), default_value) };
+
+        Ok(Self { id })
+    }
+
+    /// Initializes an attribute without a default value, so that [`Attribute::is_set`] can tell
+    /// whether it's set in the diagram, and [`Attribute::read_u32_or`]/[`Attribute::read_f64_or`]
+    /// can fall back to a default when it isn't.
+    ///
+    /// The simulator returns the default value of unset attributes, without saying whether the
+    /// attribute was set. An optional attribute uses NaN as its default, which no numeric
+    /// attribute value can be, so a NaN read means "unset". (An attribute set to a value that
+    /// isn't a number reads as NaN too, so it's also treated as unset.)
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::attr::Attribute;
+    ///
+    /// // "0" is a valid address, so 0 can't stand for "unset".
+    /// let address = Attribute::optional("address").read_u32_or(0x50);
+    /// let vref = Attribute::optional("vref").read_f64_or(3.3);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a NUL byte.
+    pub fn optional(name: &str) -> Self {
+        Self::new(name, f64::NAN)
+    }
+
+    /// Returns false if the attribute isn't set in the diagram. Only attributes created with
+    /// [`Attribute::optional`] can be detected as unset. Other attributes read their default
+    /// value instead, so this returns true for them (unless the default is NaN).
+    pub fn is_set(&self) -> bool {
+        !self.read_f64().is_nan()
+    }
+
+    /// Reads the attribute as an integer, or returns `default` if it isn't set. See
+    /// [`Attribute::optional`].
+    pub fn read_u32_or(&self, default: u32) -> u32 {
+        if self.is_set() {
+            self.read_u32()
+        } else {
+            default
+        }
+    }
+
+    /// Reads the attribute as a floating point number, or returns `default` if it isn't set. See
+    /// [`Attribute::optional`].
+    pub fn read_f64_or(&self, default: f64) -> f64 {
+        let value = self.read_f64();
+        if value.is_nan() {
+            default
+        } else {
+            value
+        }
+    }
+
+    /// Reads the attribute as an integer. If the attribute isn't set in the diagram, returns the
+    /// default value passed to [`Attribute::new`], converted to an integer.
+    pub fn read_u32(&self) -> u32 {
+        unsafe { attrRead(self.id) }
+    }
+
+    /// Reads the attribute as a signed integer. The simulator returns the value as a 32-bit
+    /// integer, so negative values such as `"-5"` read back correctly.
+    pub fn read_i32(&self) -> i32 {
+        self.read_u32() as i32
+    }
+
+    /// Reads the attribute as an integer and converts it to `T`, e.g. an enum or a size.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::attr::Attribute;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// // "mode": "2" selects PinMode::InputPullup.
+    /// let mode = Attribute::new("mode", 0.0).read::<PinMode>().unwrap_or(PinMode::Input);
+    /// let pin = Pin::new("IN", mode);
+    /// let bits = Attribute::new("bits", 8.0).read::<u8>().expect("bits must fit in a u8");
+    /// ```
+    pub fn read<T: TryFrom<u32>>(&self) -> Result<T, T::Error> {
+        T::try_from(self.read_u32())
+    }
+
+    /// Reads the attribute as a floating point number, e.g. a voltage threshold.
+    ///
+    /// The value is returned as the simulator reports it, so it can be infinite, or NaN (e.g. if
+    /// the attribute isn't a number). Use [`Attribute::read_f64_clamped`] to always get a usable
+    /// value.
+    pub fn read_f64(&self) -> f64 {
+        unsafe { attrReadFloat(self.id) }
+    }
+
+    /// Like [`Attribute::read_f64`], converted to `f32`. Values outside the `f32` range become
+    /// infinite.
+    pub fn read_f32(&self) -> f32 {
+        self.read_f64() as f32
+    }
+
+    /// Reads the attribute as a floating point number, limited to `min..=max`. Infinite values
+    /// are clamped like any other value, and NaN reads as `min`.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::attr::Attribute;
+    ///
+    /// // "vref": "7" reads as 5.0, "vref": "abc" reads as 0.0.
+    /// let vref = Attribute::new("vref", 3.3).read_f64_clamped(0.0, 5.0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min > max`, or either bound is NaN.
+    pub fn read_f64_clamped(&self, min: f64, max: f64) -> f64 {
+        assert!(min <= max, "invalid range: {}..={}", min, max);
+        let value = self.read_f64();
+        if value.is_nan() {
+            min
+        } else {
+            value.clamp(min, max)
+        }
+    }
+
+    /// Reads the attribute once, and returns a [`CachedAttribute`] that keeps the value until
+    /// it's polled again.
+    pub fn cached<T: AttributeValue>(self) -> CachedAttribute<T> {
+        let value = T::read_from(&self);
+        CachedAttribute {
+            attribute: self,
+            value,
+        }
+    }
+
+    pub fn get_id(&self) -> AttrId {
+        self.id
+    }
+}
+
+/// A type an [`Attribute`] can be read as, for [`CachedAttribute`].
+pub trait AttributeValue: Copy + PartialEq {
+    fn read_from(attribute: &Attribute) -> Self;
+
+    /// Returns true if `new` is a different value than `old`.
+    fn changed(old: Self, new: Self) -> bool {
+        old != new
+    }
+}
+
+impl AttributeValue for u32 {
+    fn read_from(attribute: &Attribute) -> Self {
+        attribute.read_u32()
+    }
+}
+
+impl AttributeValue for i32 {
+    fn read_from(attribute: &Attribute) -> Self {
+        attribute.read_i32()
+    }
+}
+
+impl AttributeValue for f32 {
+    fn read_from(attribute: &Attribute) -> Self {
+        attribute.read_f32()
+    }
+
+    // NaN (e.g. an unset optional attribute) doesn't equal itself, but it didn't change.
+    fn changed(old: Self, new: Self) -> bool {
+        old != new && !(old.is_nan() && new.is_nan())
+    }
+}
+
+impl AttributeValue for f64 {
+    fn read_from(attribute: &Attribute) -> Self {
+        attribute.read_f64()
+    }
+
+    fn changed(old: Self, new: Self) -> bool {
+        old != new && !(old.is_nan() && new.is_nan())
+    }
+}
+
+/// An attribute value that is only read from the simulator when polled, see
+/// [`Attribute::cached`].
+///
+/// Example, reacting to a knob once per millisecond:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::attr::Attribute;
+/// use wokwi_chips_api::timer::Timer;
+///
+/// let mut brightness = Attribute::new("brightness", 50.0).cached::<u32>();
+/// let knob = Timer::periodic(Duration::from_millis(1), move || {
+///     if let Some(value) = brightness.poll() {
+///         println!("Brightness changed to {}", value);
+///     }
+/// });
+/// ```
+pub struct CachedAttribute<T: AttributeValue> {
+    attribute: Attribute,
+    value: T,
+}
+
+impl<T: AttributeValue> CachedAttribute<T> {
+    /// Returns the value read by the last poll, without reading the attribute.
+    pub fn get(&self) -> T {
+        self.value
+    }
+
+    /// Reads the attribute, and returns the new value if it changed since the last poll.
+    pub fn poll(&mut self) -> Option<T> {
+        let value = T::read_from(&self.attribute);
+        if !T::changed(self.value, value) {
+            return None;
+        }
+        self.value = value;
+        Some(value)
+    }
+
+    pub fn attribute(&self) -> &Attribute {
+        &self.attribute
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use crate::pin::PinMode;
+
+    #[test]
+    fn typed_reads_convert_the_attribute_value() {
+        mock::set_attribute("address", 66.0);
+        mock::set_attribute("offset", -5.0);
+        mock::set_attribute("mode", 2.0);
+        mock::set_attribute("bits", 300.0);
+        let address = Attribute::new("address", 0.0);
+        assert_eq!(address.read_u32(), 0x42);
+        assert_eq!(address.read::<u8>(), Ok(0x42));
+        assert_eq!(Attribute::new("offset", 0.0).read_i32(), -5);
+        assert_eq!(
+            Attribute::new("mode", 0.0).read::<PinMode>(),
+            Ok(PinMode::InputPullup)
+        );
+        assert!(Attribute::new("bits", 8.0).read::<u8>().is_err());
+    }
+
+    #[test]
+    fn unset_attributes_read_their_default() {
+        let threshold = Attribute::new("threshold", 2.5);
+        assert_eq!(threshold.read_f64(), 2.5);
+        assert_eq!(threshold.read_u32(), 2);
+        assert!(threshold.is_set());
+
+        let address = Attribute::optional("address");
+        assert!(!address.is_set());
+        assert_eq!(address.read_u32_or(0x50), 0x50);
+        assert_eq!(address.read_f64_or(3.3), 3.3);
+    }
+
+    #[test]
+    fn every_read_sees_the_current_value() {
+        let level = Attribute::new("level", 1.0);
+        assert_eq!(level.read_u32(), 1);
+        mock::change_attribute(level.get_id(), 7.0);
+        assert_eq!(level.read_u32(), 7);
+    }
+
+    #[test]
+    fn clamped_reads_stay_within_the_range() {
+        let vref = Attribute::new("vref", 3.3);
+        assert_eq!(vref.read_f64_clamped(0.0, 5.0), 3.3);
+        mock::change_attribute(vref.get_id(), 7.0);
+        assert_eq!(vref.read_f64_clamped(0.0, 5.0), 5.0);
+        mock::change_attribute(vref.get_id(), f64::NEG_INFINITY);
+        assert_eq!(vref.read_f64_clamped(0.0, 5.0), 0.0);
+        mock::change_attribute(vref.get_id(), f64::NAN);
+        assert_eq!(vref.read_f64_clamped(1.0, 5.0), 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid range: 5..=0")]
+    fn clamped_reads_reject_an_empty_range() {
+        Attribute::new("vref", 3.3).read_f64_clamped(5.0, 0.0);
+    }
+
+    #[test]
+    fn cached_attributes_change_only_when_polled() {
+        let attribute = Attribute::optional("brightness");
+        let id = attribute.get_id();
+        let mut brightness = attribute.cached::<f64>();
+        // NaN doesn't equal itself, but an unset attribute didn't change.
+        assert_eq!(brightness.poll(), None);
+
+        mock::change_attribute(id, 50.0);
+        assert!(brightness.get().is_nan());
+        assert_eq!(brightness.poll(), Some(50.0));
+        assert_eq!(brightness.poll(), None);
+        assert_eq!(brightness.get(), 50.0);
+    }
+}
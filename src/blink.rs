@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::pin::Pin;
+use crate::timer::Timer;
+
+/// Blinks a pin: toggles it every half `period`, so it is high for half the period and low for
+/// the other half.
+///
+/// The blinker owns the pin and its timer. Dropping the blinker stops the timer and releases the
+/// pin.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::blink::Blinker;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let led = Pin::new("LED", PinMode::Output);
+/// let mut blinker = Blinker::new(led, Duration::from_millis(500));
+/// blinker.start();
+/// // Later:
+/// blinker.set_period(Duration::from_millis(100));
+/// ```
+///
+pub struct Blinker {
+    pin: Rc<Pin>,
+    timer: Timer,
+    period: Duration,
+    running: bool,
+}
+
+fn check_period(period: Duration) {
+    assert!(
+        period >= Duration::from_nanos(2),
+        "blink period {:?} is shorter than 2 ns",
+        period
+    );
+}
+
+impl Blinker {
+    /// Creates a blinker for `pin`. The pin doesn't change until [`Blinker::start`] is called.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is shorter than 2 ns, as the pin toggles every half period and the
+    /// simulator's timer resolution is 1 ns.
+    pub fn new(pin: Pin, period: Duration) -> Self {
+        check_period(period);
+        let pin = Rc::new(pin);
+        let timer_pin = pin.clone();
+        let timer = Timer::new(move || timer_pin.toggle());
+
+        Self {
+            pin,
+            timer,
+            period,
+            running: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.timer.start(self.period / 2, true);
+        self.running = true;
+    }
+
+    /// Stops blinking, leaving the pin at its current value.
+    pub fn stop(&mut self) {
+        self.timer.stop();
+        self.running = false;
+    }
+
+    /// Changes the blink period. If the blinker is running, it restarts with the new period.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is shorter than 2 ns, like [`Blinker::new`].
+    pub fn set_period(&mut self, period: Duration) {
+        check_period(period);
+        self.period = period;
+        if self.running {
+            self.start();
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    pub fn pin(&self) -> &Pin {
+        &self.pin
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use crate::pin::{PinMode, PinValue};
+
+    fn blinker(period: Duration) -> Blinker {
+        Blinker::new(Pin::new("LED", PinMode::OutputLow), period)
+    }
+
+    #[test]
+    fn the_pin_toggles_every_half_period() {
+        let mut blinker = blinker(Duration::from_millis(10));
+        mock::advance(Duration::from_millis(20));
+        assert_eq!(mock::pin_value("LED"), PinValue::Low);
+
+        blinker.start();
+        mock::advance(Duration::from_millis(5));
+        assert_eq!(mock::pin_value("LED"), PinValue::High);
+        mock::advance(Duration::from_millis(5));
+        assert_eq!(mock::pin_value("LED"), PinValue::Low);
+        mock::advance(Duration::from_millis(5));
+        assert_eq!(mock::pin_value("LED"), PinValue::High);
+    }
+
+    #[test]
+    fn stop_leaves_the_pin_at_its_current_value() {
+        let mut blinker = blinker(Duration::from_millis(10));
+        blinker.start();
+        mock::advance(Duration::from_millis(5));
+        blinker.stop();
+        mock::advance(Duration::from_millis(20));
+        assert_eq!(mock::pin_value("LED"), PinValue::High);
+    }
+
+    #[test]
+    fn set_period_restarts_a_running_blinker() {
+        let mut blinker = blinker(Duration::from_millis(10));
+        blinker.start();
+        mock::advance(Duration::from_millis(4));
+        blinker.set_period(Duration::from_millis(2));
+        assert_eq!(blinker.period(), Duration::from_millis(2));
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(mock::pin_value("LED"), PinValue::High);
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(mock::pin_value("LED"), PinValue::Low);
+    }
+
+    #[test]
+    #[should_panic(expected = "blink period 0ns is shorter than 2 ns")]
+    fn a_zero_period_panics() {
+        blinker(Duration::ZERO);
+    }
+}
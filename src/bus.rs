@@ -0,0 +1,371 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::pin::{Pin, PinMode, PinValue, WatchEdge};
+use crate::timer::Timer;
+
+/// The order in which the bits of a value are mapped to pins (or clocked out).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    /// The least significant bit comes first.
+    #[default]
+    LsbFirst,
+    /// The most significant bit comes first.
+    MsbFirst,
+}
+
+/// A group of pins that are read and written together as a single integer value, e.g. an 8-bit
+/// data bus.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::bus::PinBus;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let mut data = PinBus::new(vec![
+///     Pin::new("D0", PinMode::Input),
+///     Pin::new("D1", PinMode::Input),
+///     Pin::new("D2", PinMode::Input),
+///     Pin::new("D3", PinMode::Input),
+/// ]);
+/// let nibble = data.read();
+/// data.set_mode_all(PinMode::Output);
+/// data.write(!nibble & 0xf);
+/// ```
+///
+pub struct PinBus {
+    pins: Vec<Pin>,
+    bit_order: BitOrder,
+}
+
+impl PinBus {
+    /// Creates a bus where the first pin is the least significant bit. A bus can have up to 32
+    /// pins.
+    pub fn new(pins: Vec<Pin>) -> Self {
+        Self::with_bit_order(pins, BitOrder::LsbFirst)
+    }
+
+    /// Creates a bus with the given bit order: with [`BitOrder::MsbFirst`], the first pin is the
+    /// most significant bit.
+    pub fn with_bit_order(pins: Vec<Pin>, bit_order: BitOrder) -> Self {
+        assert!(pins.len() <= 32, "a PinBus can have at most 32 pins");
+        Self { pins, bit_order }
+    }
+
+    fn bit_index(&self, pin_index: usize) -> usize {
+        match self.bit_order {
+            BitOrder::LsbFirst => pin_index,
+            BitOrder::MsbFirst => self.pins.len() - 1 - pin_index,
+        }
+    }
+
+    /// Drives each pin from the corresponding bit of `value`. Bits that don't map to a pin are
+    /// ignored.
+    pub fn write(&self, value: u32) {
+        for (index, pin) in self.pins.iter().enumerate() {
+            pin.write(PinValue::from(value & (1 << self.bit_index(index)) != 0));
+        }
+    }
+
+    /// Drives every pin of the bus to `value`, e.g. to release a bus by driving it high.
+    pub fn set_all(&self, value: PinValue) {
+        for pin in &self.pins {
+            pin.write(value);
+        }
+    }
+
+    /// Drives only the pins whose bit is set in `mask`, from the corresponding bit of `value`.
+    /// The other pins are left untouched (they aren't written at all), so a chip can update part
+    /// of a bus, e.g. the low address byte, while keeping the rest.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::bus::PinBus;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// let address = PinBus::new(Pin::array("A", 0..16, PinMode::OutputLow).unwrap());
+    /// address.write(0x1234);
+    /// address.write_masked(0x00ab, 0x00ff);
+    /// assert_eq!(address.read_all(), 0x12ab);
+    /// ```
+    pub fn write_masked(&self, value: u32, mask: u32) {
+        for (index, pin) in self.pins.iter().enumerate() {
+            let bit = 1 << self.bit_index(index);
+            if mask & bit != 0 {
+                pin.write(PinValue::from(value & bit != 0));
+            }
+        }
+    }
+
+    /// Reads all the pins, as a packed value where each pin is the corresponding bit.
+    pub fn read(&self) -> u32 {
+        self.pins
+            .iter()
+            .enumerate()
+            .filter(|(_, pin)| pin.is_high())
+            .fold(0, |value, (index, _)| value | 1 << self.bit_index(index))
+    }
+
+    /// Same as [`PinBus::read`], as the counterpart of [`PinBus::write_masked`] for code that
+    /// reads the whole bus after updating part of it.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::bus::PinBus;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// let data = PinBus::new(Pin::array("D", 0..8, PinMode::OutputLow).unwrap());
+    /// data.write_masked(0x0f, 0x0f);
+    /// assert_eq!(data.read_all(), 0x0f);
+    /// ```
+    pub fn read_all(&self) -> u32 {
+        self.read()
+    }
+
+    /// Watches every pin of the bus, calling `callback` with the new value of the whole bus (as
+    /// returned by [`PinBus::read`]) when any of them changes.
+    ///
+    /// The simulator reports each pin on its own, so pins that change together would otherwise
+    /// call back once per pin, with the intermediate values in between. Instead, the bus value is
+    /// read after a zero-length timer, which fires once the simulator is done with all the changes
+    /// at the current simulation time: changes at the same time are coalesced into a single call.
+    /// `callback` isn't called if the bus ends up with the value it last reported, e.g. when a
+    /// pin goes high and back low at the same time.
+    ///
+    /// Like [`Pin::watch`], the watch keeps going until [`Pin::unwatch`] is called on the pins or
+    /// the bus is dropped.
+    ///
+    /// Returns false if the simulator refused to watch one of the pins. The other pins aren't
+    /// watched then either, so `callback` is never called.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::bus::PinBus;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// let data = PinBus::new(Pin::array("D", 0..8, PinMode::Input).unwrap());
+    /// data.watch(|value| {
+    ///     println!("data bus: {:02x}", value);
+    /// });
+    /// ```
+    pub fn watch<F>(&self, mut callback: F) -> bool
+    where
+        F: FnMut(u32) + 'static,
+    {
+        let bits: Vec<_> = self
+            .pins
+            .iter()
+            .enumerate()
+            .map(|(index, pin)| (pin.get_id(), 1 << self.bit_index(index)))
+            .collect();
+        let last_value = Cell::new(self.read());
+        let timer = Rc::new(Timer::new(move || {
+            let value = bits
+                .iter()
+                .filter(|&&(pin_id, _)| Pin::from_id(pin_id).is_high())
+                .fold(0, |value, &(_, bit)| value | bit);
+            if last_value.replace(value) != value {
+                callback(value);
+            }
+        }));
+
+        // Each watch callback holds the timer, so it goes away together with the last watch. The
+        // watches are only handed to the pins once they all succeeded: if one fails, dropping the
+        // guards removes the others.
+        let mut guards = Vec::with_capacity(self.pins.len());
+        for pin in &self.pins {
+            let timer = timer.clone();
+            let guard = pin.watch_scoped(WatchEdge::Both, move |_, _| {
+                if !timer.is_running() {
+                    timer.start(Duration::ZERO, false);
+                }
+            });
+            if !guard.is_active() {
+                return false;
+            }
+            guards.push(guard);
+        }
+        for (pin, guard) in self.pins.iter().zip(guards) {
+            pin.keep_watch(guard);
+        }
+        true
+    }
+
+    pub fn set_mode_all(&mut self, mode: PinMode) {
+        for pin in &mut self.pins {
+            pin.set_mode(mode);
+        }
+    }
+
+    pub fn pins(&self) -> &[Pin] {
+        &self.pins
+    }
+
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use std::cell::RefCell;
+
+    fn bus(bit_order: BitOrder, mode: PinMode) -> PinBus {
+        PinBus::with_bit_order(Pin::array("D", 0..4, mode).unwrap(), bit_order)
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let bus = bus(BitOrder::LsbFirst, PinMode::OutputLow);
+        for value in [0b0000, 0b0001, 0b1010, 0b0101, 0b1111] {
+            bus.write(value);
+            assert_eq!(bus.read(), value);
+        }
+        bus.write(0b0001);
+        assert_eq!(mock::pin_value("D0"), PinValue::High);
+        assert_eq!(mock::pin_value("D3"), PinValue::Low);
+        // Bits above the bus width are ignored.
+        bus.write(0xf0);
+        assert_eq!(bus.read(), 0);
+    }
+
+    #[test]
+    fn msb_first_maps_the_first_pin_to_the_top_bit() {
+        let bus = bus(BitOrder::MsbFirst, PinMode::OutputLow);
+        bus.write(0b0001);
+        assert_eq!(mock::pin_value("D0"), PinValue::Low);
+        assert_eq!(mock::pin_value("D3"), PinValue::High);
+        assert_eq!(bus.read(), 0b0001);
+    }
+
+    #[test]
+    fn set_mode_all_switches_between_input_and_output() {
+        let mut bus = bus(BitOrder::LsbFirst, PinMode::Input);
+        mock::set_input("D1", PinValue::High);
+        mock::set_input("D2", PinValue::High);
+        assert_eq!(bus.read(), 0b0110);
+
+        bus.set_mode_all(PinMode::Output);
+        assert_eq!(mock::pin_mode("D3"), PinMode::Output);
+        bus.write(0b1001);
+        assert_eq!(bus.read(), 0b1001);
+    }
+
+    #[test]
+    fn write_masked_leaves_the_unmasked_pins_untouched() {
+        let bus = bus(BitOrder::LsbFirst, PinMode::OutputLow);
+        bus.write(0b1010);
+        let recorder = mock::Recorder::start();
+        bus.write_masked(0b0101, 0b0011);
+        let recording = recorder.stop();
+
+        assert_eq!(bus.read_all(), 0b1001);
+        assert_eq!(mock::pin_value("D2"), PinValue::Low);
+        assert_eq!(mock::pin_value("D3"), PinValue::High);
+        // Only the masked pins are written.
+        let written: Vec<_> = recording.writes().iter().map(|event| &event.pin).collect();
+        assert_eq!(written, ["D0", "D1"]);
+    }
+
+    #[test]
+    fn set_all_drives_every_pin() {
+        let bus = bus(BitOrder::MsbFirst, PinMode::OutputLow);
+        bus.set_all(PinValue::High);
+        assert_eq!(bus.read_all(), 0b1111);
+        bus.set_all(PinValue::Low);
+        assert_eq!(bus.read_all(), 0);
+    }
+
+    #[test]
+    fn write_masked_follows_the_bit_order() {
+        let bus = bus(BitOrder::MsbFirst, PinMode::OutputLow);
+        bus.write_masked(0b1111, 0b1000);
+        assert_eq!(mock::pin_value("D0"), PinValue::High);
+        assert_eq!(mock::pin_value("D1"), PinValue::Low);
+        assert_eq!(bus.read_all(), 0b1000);
+        // Mask bits that don't map to a pin are ignored.
+        bus.write_masked(0xff, 0xf0);
+        assert_eq!(bus.read_all(), 0b1000);
+    }
+
+    fn watched_values(bus: &PinBus) -> Rc<RefCell<Vec<u32>>> {
+        let values = Rc::new(RefCell::new(Vec::new()));
+        let watch_values = values.clone();
+        assert!(bus.watch(move |value| watch_values.borrow_mut().push(value)));
+        values
+    }
+
+    #[test]
+    fn simultaneous_changes_call_the_watch_once() {
+        let bus = PinBus::new(Pin::array("D", 0..8, PinMode::Input).unwrap());
+        let values = watched_values(&bus);
+        for index in 0..8 {
+            mock::set_input(&format!("D{}", index), PinValue::High);
+        }
+        assert!(values.borrow().is_empty());
+        mock::advance(Duration::ZERO);
+        assert_eq!(*values.borrow(), [0xff]);
+        mock::advance(Duration::from_micros(1));
+        assert_eq!(*values.borrow(), [0xff]);
+    }
+
+    #[test]
+    fn changes_at_different_times_call_the_watch_each_time() {
+        let bus = bus(BitOrder::LsbFirst, PinMode::Input);
+        let values = watched_values(&bus);
+        mock::set_input("D0", PinValue::High);
+        mock::set_input("D2", PinValue::High);
+        mock::advance(Duration::from_micros(1));
+        mock::set_input("D0", PinValue::Low);
+        mock::advance(Duration::from_micros(1));
+        mock::set_input("D2", PinValue::Low);
+        mock::advance(Duration::from_micros(1));
+        assert_eq!(*values.borrow(), [0b0101, 0b0100, 0b0000]);
+    }
+
+    #[test]
+    fn a_change_undone_at_the_same_time_does_not_call_the_watch() {
+        let bus = bus(BitOrder::LsbFirst, PinMode::Input);
+        let values = watched_values(&bus);
+        mock::set_input("D1", PinValue::High);
+        mock::set_input("D1", PinValue::Low);
+        mock::advance(Duration::from_micros(1));
+        assert!(values.borrow().is_empty());
+    }
+
+    #[test]
+    fn a_refused_watch_leaves_no_pin_watched() {
+        let bus = bus(BitOrder::LsbFirst, PinMode::Input);
+        mock::set_watch_refused("D2", true);
+        let values = Rc::new(RefCell::new(Vec::new()));
+        let watch_values = values.clone();
+        assert!(!bus.watch(move |value| watch_values.borrow_mut().push(value)));
+        for index in 0..4 {
+            assert!(!mock::is_watched(&format!("D{}", index)));
+        }
+        mock::set_input("D0", PinValue::High);
+        mock::advance(Duration::from_micros(1));
+        assert!(values.borrow().is_empty());
+
+        // Watches added to the pins on their own aren't affected.
+        mock::set_watch_refused("D2", false);
+        assert!(bus.pins()[0].watch(WatchEdge::Both, |_, _| {}));
+        mock::set_watch_refused("D2", true);
+        assert!(!bus.watch(|_| {}));
+        assert!(mock::is_watched("D0"));
+    }
+}
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! The chip entry point.
+//!
+//! The simulator starts a chip by calling the `chipInit` function it exports, once, when the
+//! simulation starts. [`wokwi_chip!`](crate::wokwi_chip) generates it.
+
+/// Exports `init` as the chip entry point.
+///
+/// This generates `#[no_mangle] pub extern "C" fn chipInit()`, which the simulator calls once,
+/// when the chip starts. It installs the panic hook (see
+/// [`set_panic_hook`](crate::println::set_panic_hook)), so that panics show up in the Wokwi
+/// console, and then calls `init`. Chips are built with the standard library, whose allocator
+/// works as is, so there's no allocator to set up.
+///
+/// This is a `macro_rules!` macro rather than a `#[wokwi_chip]` attribute, which would need a
+/// separate proc-macro crate.
+///
+/// Example, a chip that inverts its input:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+/// use wokwi_chips_api::wokwi_chip;
+///
+/// fn init() {
+///     let output = Pin::new("OUT", PinMode::Output);
+///     let input = Pin::new("IN", PinMode::Input);
+///     output.write(!input.read());
+///     input.watch(WatchEdge::Both, move |_, value| output.write(!value));
+///     // The watch lasts as long as the input pin.
+///     std::mem::forget(input);
+/// }
+///
+/// wokwi_chip!(init);
+/// ```
+#[macro_export]
+macro_rules! wokwi_chip {
+    ($init:path) => {
+        /// The chip entry point, called by the simulator once when the chip starts.
+        #[no_mangle]
+        pub extern "C" fn chipInit() {
+            $crate::println::set_panic_hook();
+            $init();
+        }
+    };
+}
@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! A single-threaded executor for async chip code.
+//!
+//! Chips run single-threaded, driven by simulator callbacks, so the executor has no thread of its
+//! own: [`run`] polls the tasks that are ready, and returns once they're all waiting for an event.
+//! When the event comes (e.g. a pin edge or a timer, see [`Pin::next_edge`] and [`sleep`]), the
+//! simulator callback wakes the task, and the task runs right away, from inside the callback.
+//!
+//! Example, blinking an LED:
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use wokwi_chips_api::executor;
+//! use wokwi_chips_api::pin::{Pin, PinMode};
+//! use wokwi_chips_api::timer::sleep;
+//!
+//! let led = Pin::new("LED", PinMode::OutputLow);
+//! executor::spawn(async move {
+//!     loop {
+//!         led.toggle();
+//!         sleep(Duration::from_millis(500)).await;
+//!     }
+//! });
+//! executor::run();
+//! ```
+//!
+//! [`Pin::next_edge`]: crate::pin::Pin::next_edge
+//! [`sleep`]: crate::timer::sleep
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+type TaskFuture = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+// Wakers only hold a pointer-sized value, so each task gets a key that is stored in its wakers
+// and used to look up the task.
+type TaskKey = usize;
+
+struct Task {
+    key: TaskKey,
+    // Taken out while the task is polled, so that the task is free to spawn other tasks.
+    future: Option<TaskFuture>,
+}
+
+thread_local! {
+    static TASKS: RefCell<Vec<Task>> = const { RefCell::new(Vec::new()) };
+    static READY: RefCell<VecDeque<TaskKey>> = const { RefCell::new(VecDeque::new()) };
+    static NEXT_TASK_KEY: Cell<TaskKey> = const { Cell::new(0) };
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn schedule(key: TaskKey) {
+    READY.with_borrow_mut(|ready| {
+        if !ready.contains(&key) {
+            ready.push_back(key);
+        }
+    });
+}
+
+fn take_future(key: TaskKey) -> Option<TaskFuture> {
+    TASKS.with_borrow_mut(|tasks| {
+        tasks
+            .iter_mut()
+            .find(|task| task.key == key)
+            .and_then(|task| task.future.take())
+    })
+}
+
+fn restore_future(key: TaskKey, future: TaskFuture) {
+    TASKS.with_borrow_mut(|tasks| {
+        if let Some(task) = tasks.iter_mut().find(|task| task.key == key) {
+            task.future = Some(future);
+        }
+    });
+}
+
+fn remove_task(key: TaskKey) {
+    TASKS.with_borrow_mut(|tasks| tasks.retain(|task| task.key != key));
+}
+
+// The wakers of a task only store its key, so cloning and dropping them is free. They must be
+// woken on the chip's thread, which is the only thread a chip has.
+const WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake_waker, wake_waker, drop_waker);
+
+fn task_waker(key: TaskKey) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(key as *const (), &WAKER_VTABLE)) }
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &WAKER_VTABLE)
+}
+
+unsafe fn wake_waker(data: *const ()) {
+    schedule(data as TaskKey);
+    run();
+}
+
+unsafe fn drop_waker(_data: *const ()) {}
+
+/// Adds a task to the executor. The task first runs on the next call to [`run`].
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    let key = NEXT_TASK_KEY.get();
+    NEXT_TASK_KEY.set(key + 1);
+    TASKS.with_borrow_mut(|tasks| {
+        tasks.push(Task {
+            key,
+            future: Some(Box::pin(future)),
+        });
+    });
+    schedule(key);
+}
+
+/// Runs the tasks that are ready, until they're all waiting for an event or done. Call it once
+/// after spawning the chip's tasks, e.g. at the end of `chipInit`; after that, tasks run when
+/// they're woken.
+///
+/// Tasks spawned or woken by a running task run before this returns. Calling `run` from inside a
+/// task does nothing. If a task panics, the panic propagates out of `run` and the task is dropped;
+/// the other tasks keep their state, and run on the next call.
+///
+/// Example:
+///
+/// ```rust
+/// use std::cell::Cell;
+/// use std::rc::Rc;
+/// use wokwi_chips_api::executor;
+///
+/// let steps = Rc::new(Cell::new(0));
+/// let task_steps = steps.clone();
+/// executor::spawn(async move {
+///     task_steps.set(task_steps.get() + 1);
+///     executor::spawn(async move {
+///         task_steps.set(task_steps.get() + 1);
+///     });
+/// });
+/// assert_eq!(steps.get(), 0);
+/// executor::run();
+/// assert_eq!(steps.get(), 2);
+/// ```
+pub fn run() {
+    if RUNNING.replace(true) {
+        return;
+    }
+    let mut guard = RunGuard { polling: None };
+    while let Some(key) = READY.with_borrow_mut(|ready| ready.pop_front()) {
+        let Some(mut future) = take_future(key) else {
+            continue;
+        };
+        let waker = task_waker(key);
+        guard.polling = Some(key);
+        let poll = future.as_mut().poll(&mut Context::from_waker(&waker));
+        guard.polling = None;
+        match poll {
+            Poll::Ready(()) => remove_task(key),
+            Poll::Pending => restore_future(key, future),
+        }
+    }
+}
+
+// Clears RUNNING when `run` returns, or when a task panics and the panic unwinds out of `run`, so
+// that later calls still run the tasks. The task that panicked is removed.
+struct RunGuard {
+    polling: Option<TaskKey>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.polling {
+            remove_task(key);
+        }
+        RUNNING.set(false);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::panic;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock;
+    use crate::timer::sleep;
+
+    #[test]
+    fn tasks_resume_when_their_sleep_ends() {
+        let steps = Rc::new(Cell::new(0));
+        let task_steps = steps.clone();
+        spawn(async move {
+            for _ in 0..3 {
+                task_steps.set(task_steps.get() + 1);
+                sleep(Duration::from_millis(10)).await;
+            }
+        });
+        run();
+        assert_eq!(steps.get(), 1);
+        mock::advance(Duration::from_millis(9));
+        assert_eq!(steps.get(), 1);
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(steps.get(), 2);
+        mock::advance(Duration::from_millis(20));
+        assert_eq!(steps.get(), 3);
+        assert!(TASKS.with_borrow(Vec::is_empty));
+    }
+
+    #[test]
+    fn a_panicking_task_doesnt_stop_the_executor() {
+        spawn(async { panic!("task failed") });
+        assert!(panic::catch_unwind(run).is_err());
+        assert!(!RUNNING.get());
+        assert!(TASKS.with_borrow(Vec::is_empty));
+
+        let ran = Rc::new(Cell::new(false));
+        let task_ran = ran.clone();
+        spawn(async move { task_ran.set(true) });
+        run();
+        assert!(ran.get());
+    }
+}
@@ -0,0 +1,685 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::ops::Range;
+
+use wokwi_chip_ll::{bufferRead, bufferWrite, framebufferInit, BufferId};
+
+/// Number of bytes per pixel in the simulator's framebuffer.
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// A pixel color. In the framebuffer, each pixel takes [`BYTES_PER_PIXEL`] bytes: red, green,
+/// blue, then alpha. Pixels are stored row by row, starting at the top left corner.
+///
+/// ```rust
+/// use wokwi_chips_api::framebuffer::Rgba;
+///
+/// let orange = Rgba::rgb(0xff, 0x80, 0x00);
+/// assert_eq!(orange.to_bytes(), [0xff, 0x80, 0x00, 0xff]);
+/// assert_eq!(Rgba::from_bytes([0xff, 0x80, 0x00, 0xff]), orange);
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const BLACK: Rgba = Rgba::rgb(0, 0, 0);
+    pub const WHITE: Rgba = Rgba::rgb(0xff, 0xff, 0xff);
+    pub const TRANSPARENT: Rgba = Rgba::new(0, 0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Creates an opaque color.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::new(r, g, b, 0xff)
+    }
+
+    /// Returns the color in the framebuffer byte order.
+    pub const fn to_bytes(self) -> [u8; BYTES_PER_PIXEL] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    pub const fn from_bytes(bytes: [u8; BYTES_PER_PIXEL]) -> Self {
+        Self::new(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+
+    /// Draws this color over `background`, using this color's alpha ("source over" blending).
+    ///
+    /// ```rust
+    /// use wokwi_chips_api::framebuffer::Rgba;
+    ///
+    /// let red = Rgba::rgb(0xff, 0, 0);
+    /// assert_eq!(red.blend_over(Rgba::BLACK), red);
+    /// assert_eq!(Rgba::TRANSPARENT.blend_over(red), red);
+    /// let half_white = Rgba::new(0xff, 0xff, 0xff, 0x80);
+    /// assert_eq!(half_white.blend_over(Rgba::BLACK), Rgba::rgb(0x80, 0x80, 0x80));
+    /// ```
+    pub fn blend_over(self, background: Rgba) -> Rgba {
+        let alpha = self.a as u32;
+        let mix = |src: u8, dst: u8| {
+            ((src as u32 * alpha + dst as u32 * (255 - alpha) + 127) / 255) as u8
+        };
+        Rgba {
+            r: mix(self.r, background.r),
+            g: mix(self.g, background.g),
+            b: mix(self.b, background.b),
+            a: (alpha + (background.a as u32 * (255 - alpha) + 127) / 255) as u8,
+        }
+    }
+}
+
+/// The display of a chip, for LCD or OLED chips.
+///
+/// The size of the display comes from the `display` section of the chip's JSON definition (e.g.
+/// `"display": { "width": 128, "height": 64 }`), so it isn't chosen by the chip code. The pixels
+/// live in the simulator's memory, and are accessed through [`Framebuffer::read`] and
+/// [`Framebuffer::write`], which are bounds-checked against the size of the display.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::framebuffer::{Framebuffer, Rgba};
+///
+/// let framebuffer = Framebuffer::new();
+/// println!("Display: {}x{}", framebuffer.width(), framebuffer.height());
+/// framebuffer.set_pixel(0, 0, Rgba::WHITE);
+/// let last_x = framebuffer.width() - 1;
+/// let last_y = framebuffer.height() - 1;
+/// framebuffer.set_pixel(last_x, last_y, Rgba::rgb(0xff, 0, 0));
+/// assert_eq!(framebuffer.get_pixel(last_x, last_y), Rgba::rgb(0xff, 0, 0));
+/// ```
+pub struct Framebuffer {
+    id: BufferId,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    /// Initializes the chip's framebuffer.
+    pub fn new() -> Self {
+        let mut width = 0;
+        let mut height = 0;
+        let id = unsafe { framebufferInit(&mut width, &mut height) };
+
+        Self { id, width, height }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the size of the framebuffer in bytes.
+    pub fn len(&self) -> usize {
+        self.width as usize * self.height as usize * BYTES_PER_PIXEL
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check_range(&self, offset: usize, len: usize) {
+        assert!(
+            offset.checked_add(len).is_some_and(|end| end <= self.len()),
+            "framebuffer access out of range: {} bytes at offset {} (size {})",
+            len,
+            offset,
+            self.len()
+        );
+    }
+
+    /// Copies `data` into the framebuffer, starting at byte `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data doesn't fit in the framebuffer.
+    pub fn write(&self, offset: usize, data: &[u8]) {
+        self.check_range(offset, data.len());
+        unsafe {
+            bufferWrite(self.id, offset as u32, data.as_ptr(), data.len() as u32);
+        }
+    }
+
+    /// Fills `data` from the framebuffer, starting at byte `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is outside the framebuffer.
+    pub fn read(&self, offset: usize, data: &mut [u8]) {
+        self.check_range(offset, data.len());
+        unsafe {
+            bufferRead(self.id, offset as u32, data.as_mut_ptr(), data.len() as u32);
+        }
+    }
+
+    fn pixel_offset(&self, x: u32, y: u32) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) is outside the {}x{} framebuffer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        (y as usize * self.width as usize + x as usize) * BYTES_PER_PIXEL
+    }
+
+    /// Sets the pixel at column `x`, row `y`. (0, 0) is the top left corner.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is outside the framebuffer.
+    pub fn set_pixel(&self, x: u32, y: u32, color: Rgba) {
+        self.write(self.pixel_offset(x, y), &color.to_bytes());
+    }
+
+    /// Returns the pixel at column `x`, row `y`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is outside the framebuffer.
+    pub fn get_pixel(&self, x: u32, y: u32) -> Rgba {
+        let mut bytes = [0; BYTES_PER_PIXEL];
+        self.read(self.pixel_offset(x, y), &mut bytes);
+        Rgba::from_bytes(bytes)
+    }
+
+    /// Sets every pixel to `color`.
+    pub fn fill(&self, color: Rgba) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+
+    /// Sets every byte of the framebuffer to zero (i.e. [`Rgba::TRANSPARENT`]).
+    pub fn clear(&self) {
+        self.fill(Rgba::TRANSPARENT);
+    }
+
+    /// Sets the pixels of the `width` x `height` rectangle with its top left corner at (`x`, `y`)
+    /// to `color`. The parts of the rectangle outside the framebuffer are ignored.
+    ///
+    /// The pixels are written from a prepared buffer rather than one by one: a full-width
+    /// rectangle (such as [`Framebuffer::fill`]) takes a single simulator call, and other
+    /// rectangles take one call per row.
+    pub fn fill_rect(&self, x: u32, y: u32, width: u32, height: u32, color: Rgba) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        if x >= x_end || y >= y_end {
+            return;
+        }
+        let row = color.to_bytes().repeat((x_end - x) as usize);
+        if x == 0 && x_end == self.width {
+            // Full-width rows are contiguous, so they can be written together.
+            let rows = row.repeat((y_end - y) as usize);
+            self.write(self.pixel_offset(0, y), &rows);
+            return;
+        }
+        for row_y in y..y_end {
+            self.write(self.pixel_offset(x, row_y), &row);
+        }
+    }
+
+    /// Returns the visible part of a `src_width` x `src_height` image drawn at (`x`, `y`), as the
+    /// column and row ranges of the image, or `None` if it's completely outside the framebuffer.
+    fn clip(
+        &self,
+        x: i32,
+        y: i32,
+        src_width: u32,
+        src_height: u32,
+    ) -> Option<(Range<u32>, Range<u32>)> {
+        let visible = |offset: i32, size: u32, limit: u32| {
+            let start = (-(offset as i64)).clamp(0, size as i64) as u32;
+            let end = (limit as i64 - offset as i64).clamp(0, size as i64) as u32;
+            (start < end).then_some(start..end)
+        };
+        Some((
+            visible(x, src_width, self.width)?,
+            visible(y, src_height, self.height)?,
+        ))
+    }
+
+    fn blit_rows(
+        &self,
+        x: i32,
+        y: i32,
+        src: &[Rgba],
+        src_width: u32,
+        src_height: u32,
+        blend: bool,
+    ) {
+        assert!(
+            src.len() >= src_width as usize * src_height as usize,
+            "the source image has {} pixels, expected {}x{}",
+            src.len(),
+            src_width,
+            src_height
+        );
+        let Some((columns, rows)) = self.clip(x, y, src_width, src_height) else {
+            return;
+        };
+        let mut bytes = vec![0; columns.len() * BYTES_PER_PIXEL];
+        for row in rows {
+            let start = (row * src_width + columns.start) as usize;
+            let pixels = &src[start..start + columns.len()];
+            let offset =
+                self.pixel_offset((x + columns.start as i32) as u32, (y + row as i32) as u32);
+            if blend {
+                self.read(offset, &mut bytes);
+            }
+            for (pixel, out) in pixels.iter().zip(bytes.chunks_exact_mut(BYTES_PER_PIXEL)) {
+                let color = if blend {
+                    pixel.blend_over(Rgba::from_bytes([out[0], out[1], out[2], out[3]]))
+                } else {
+                    *pixel
+                };
+                out.copy_from_slice(&color.to_bytes());
+            }
+            self.write(offset, &bytes);
+        }
+    }
+
+    /// Copies a `src_width` x `src_height` image, stored row by row in `src`, to the framebuffer,
+    /// with its top left corner at (`x`, `y`). The offsets can be negative, and the image can be
+    /// larger than the framebuffer: the parts outside the framebuffer are clipped. The alpha
+    /// channel is copied as is; see [`Framebuffer::blit_alpha`] for blending.
+    ///
+    /// Example, a 2x2 image drawn partly off the top left corner:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::framebuffer::{Framebuffer, Rgba};
+    ///
+    /// let framebuffer = Framebuffer::new();
+    /// let red = Rgba::rgb(0xff, 0, 0);
+    /// let image = [Rgba::BLACK, Rgba::BLACK, Rgba::BLACK, red];
+    /// framebuffer.blit(-1, -1, &image, 2, 2);
+    /// assert_eq!(framebuffer.get_pixel(0, 0), red);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` has fewer than `src_width * src_height` pixels.
+    pub fn blit(&self, x: i32, y: i32, src: &[Rgba], src_width: u32, src_height: u32) {
+        self.blit_rows(x, y, src, src_width, src_height, false);
+    }
+
+    /// Like [`Framebuffer::blit`], but blends the image over the current framebuffer contents
+    /// using its alpha channel (see [`Rgba::blend_over`]).
+    pub fn blit_alpha(&self, x: i32, y: i32, src: &[Rgba], src_width: u32, src_height: u32) {
+        self.blit_rows(x, y, src, src_width, src_height, true);
+    }
+
+    /// Draws `text` with the built-in 5x7 font, with the top left corner of the first character
+    /// at (`x`, `y`), and returns the `x` coordinate after the last character.
+    ///
+    /// Each character takes a [`GLYPH_ADVANCE`] x [`GLYPH_HEIGHT`] cell: the glyph, followed by
+    /// a column of spacing. The cells are filled with `background`, or left as they are if it's
+    /// `None`. Text doesn't wrap: the parts outside the framebuffer are clipped. Only printable
+    /// ASCII characters have glyphs; other characters are drawn as `?`.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::framebuffer::{Framebuffer, Rgba, GLYPH_HEIGHT};
+    ///
+    /// let framebuffer = Framebuffer::new();
+    /// framebuffer.fill(Rgba::BLACK);
+    /// framebuffer.draw_text(0, 0, "Hello,", Rgba::WHITE, None);
+    /// let green = Rgba::rgb(0, 0xff, 0);
+    /// framebuffer.draw_text(0, GLYPH_HEIGHT as i32 + 1, "Wokwi!", green, Some(Rgba::BLACK));
+    /// ```
+    pub fn draw_text(
+        &self,
+        x: i32,
+        y: i32,
+        text: &str,
+        color: Rgba,
+        background: Option<Rgba>,
+    ) -> i32 {
+        let glyphs: Vec<_> = text.chars().map(glyph).collect();
+        let width = glyphs.len() as u32 * GLYPH_ADVANCE;
+        let mut pixels =
+            vec![background.unwrap_or(Rgba::TRANSPARENT); (width * GLYPH_HEIGHT) as usize];
+        for (index, columns) in glyphs.iter().enumerate() {
+            for (column, bits) in columns.iter().enumerate() {
+                let pixel_x = index * GLYPH_ADVANCE as usize + column;
+                for row in 0..GLYPH_HEIGHT as usize {
+                    if bits & (1 << row) != 0 {
+                        pixels[row * width as usize + pixel_x] = color;
+                    }
+                }
+            }
+        }
+        if background.is_some() {
+            self.blit(x, y, &pixels, width, GLYPH_HEIGHT);
+        } else {
+            // The unlit pixels are transparent, so they keep the framebuffer contents.
+            self.blit_alpha(x, y, &pixels, width, GLYPH_HEIGHT);
+        }
+        x.saturating_add(width as i32)
+    }
+
+    pub fn get_id(&self) -> BufferId {
+        self.id
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Width of the glyphs of the built-in font.
+pub const GLYPH_WIDTH: u32 = 5;
+/// Height of the glyphs of the built-in font.
+pub const GLYPH_HEIGHT: u32 = 7;
+/// Horizontal distance between characters drawn by [`Framebuffer::draw_text`]: the glyph width
+/// plus one column of spacing.
+pub const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Returns the glyph of `c` in the built-in 5x7 font, as one byte per column, left to right. Bit
+/// 0 of each byte is the top row. Characters outside printable ASCII get the `?` glyph.
+///
+/// ```rust
+/// use wokwi_chips_api::framebuffer::glyph;
+///
+/// // The vertical bars of 'H', joined by a horizontal bar in the middle row.
+/// assert_eq!(glyph('H'), [0x7f, 0x08, 0x08, 0x08, 0x7f]);
+/// assert_eq!(glyph('é'), glyph('?'));
+/// ```
+pub fn glyph(c: char) -> [u8; GLYPH_WIDTH as usize] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    FONT_5X7[index]
+}
+
+// Printable ASCII, from ' ' (0x20) to '~' (0x7e).
+const FONT_5X7: [[u8; GLYPH_WIDTH as usize]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x14, 0x08, 0x3e, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn framebuffer(width: u32, height: u32) -> Framebuffer {
+        mock::set_framebuffer_size(width, height);
+        Framebuffer::new()
+    }
+
+    // Returns every pixel, row by row.
+    fn pixels(framebuffer: &Framebuffer) -> Vec<Vec<Rgba>> {
+        (0..framebuffer.height())
+            .map(|y| {
+                (0..framebuffer.width())
+                    .map(|x| framebuffer.get_pixel(x, y))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pixels_read_back_at_the_corners() {
+        let framebuffer = framebuffer(4, 3);
+        let corners = [(0, 0), (3, 0), (0, 2), (3, 2)];
+        for (index, &(x, y)) in corners.iter().enumerate() {
+            framebuffer.set_pixel(x, y, Rgba::new(index as u8, 0x10, 0x20, 0xff));
+        }
+        for (index, &(x, y)) in corners.iter().enumerate() {
+            assert_eq!(
+                framebuffer.get_pixel(x, y),
+                Rgba::new(index as u8, 0x10, 0x20, 0xff)
+            );
+        }
+        assert_eq!(framebuffer.get_pixel(1, 1), Rgba::TRANSPARENT);
+    }
+
+    #[test]
+    fn pixels_are_stored_row_by_row_as_rgba() {
+        let framebuffer = framebuffer(2, 2);
+        framebuffer.set_pixel(1, 0, Rgba::new(1, 2, 3, 4));
+        framebuffer.set_pixel(0, 1, Rgba::new(5, 6, 7, 8));
+        assert_eq!(
+            mock::framebuffer(),
+            [0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "pixel (4, 0) is outside the 4x3 framebuffer")]
+    fn set_pixel_past_the_right_edge_panics() {
+        framebuffer(4, 3).set_pixel(4, 0, Rgba::WHITE);
+    }
+
+    #[test]
+    #[should_panic(expected = "pixel (0, 3) is outside the 4x3 framebuffer")]
+    fn get_pixel_past_the_bottom_edge_panics() {
+        framebuffer(4, 3).get_pixel(0, 3);
+    }
+
+    #[test]
+    fn fill_sets_every_pixel_and_clear_resets_them() {
+        let framebuffer = framebuffer(5, 3);
+        let red = Rgba::rgb(0xff, 0, 0);
+        framebuffer.fill(red);
+        assert_eq!(pixels(&framebuffer), vec![vec![red; 5]; 3]);
+        framebuffer.clear();
+        assert_eq!(pixels(&framebuffer), vec![vec![Rgba::TRANSPARENT; 5]; 3]);
+    }
+
+    #[test]
+    fn fill_rect_is_clipped_at_the_edges() {
+        let framebuffer = framebuffer(4, 3);
+        let (o, r) = (Rgba::TRANSPARENT, Rgba::rgb(0xff, 0, 0));
+        framebuffer.fill_rect(2, 1, 5, 5, r);
+        assert_eq!(
+            pixels(&framebuffer),
+            [[o, o, o, o], [o, o, r, r], [o, o, r, r]]
+        );
+
+        // Completely outside the framebuffer
+        framebuffer.fill_rect(4, 0, 2, 2, Rgba::WHITE);
+        framebuffer.fill_rect(0, 3, 2, 2, Rgba::WHITE);
+        framebuffer.fill_rect(0, 0, 0, 3, Rgba::WHITE);
+        assert_eq!(
+            pixels(&framebuffer),
+            [[o, o, o, o], [o, o, r, r], [o, o, r, r]]
+        );
+    }
+
+    #[test]
+    fn blit_clips_an_image_at_negative_offsets() {
+        let framebuffer = framebuffer(3, 3);
+        let o = Rgba::TRANSPARENT;
+        let [a, b, c, d] = [1, 2, 3, 4].map(|value| Rgba::rgb(value, 0, 0));
+        framebuffer.blit(-1, -1, &[a, b, c, d], 2, 2);
+        assert_eq!(pixels(&framebuffer), [[d, o, o], [o, o, o], [o, o, o]]);
+        framebuffer.blit(2, 1, &[a, b, c, d], 2, 2);
+        assert_eq!(pixels(&framebuffer), [[d, o, o], [o, o, a], [o, o, c]]);
+        // Completely outside the framebuffer
+        framebuffer.blit(-2, 0, &[a, b, c, d], 2, 2);
+        framebuffer.blit(0, 3, &[a, b, c, d], 2, 2);
+        assert_eq!(pixels(&framebuffer), [[d, o, o], [o, o, a], [o, o, c]]);
+    }
+
+    #[test]
+    fn blit_clips_an_image_larger_than_the_framebuffer() {
+        let framebuffer = framebuffer(3, 2);
+        // A 5x4 image, with each pixel's red channel set to its index.
+        let image: Vec<_> = (0..20).map(|index| Rgba::rgb(index, 0, 0)).collect();
+        framebuffer.blit(-1, -1, &image, 5, 4);
+        let pixel = |index| Rgba::rgb(index, 0, 0);
+        assert_eq!(
+            pixels(&framebuffer),
+            [
+                [pixel(6), pixel(7), pixel(8)],
+                [pixel(11), pixel(12), pixel(13)]
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the source image has 3 pixels, expected 2x2")]
+    fn blit_from_a_short_source_panics() {
+        framebuffer(3, 3).blit(0, 0, &[Rgba::WHITE; 3], 2, 2);
+    }
+
+    #[test]
+    fn blit_alpha_blends_over_the_framebuffer() {
+        let framebuffer = framebuffer(3, 1);
+        let red = Rgba::rgb(0xff, 0, 0);
+        framebuffer.fill(red);
+        let half_white = Rgba::new(0xff, 0xff, 0xff, 0x80);
+        framebuffer.blit_alpha(-1, 0, &[Rgba::WHITE, half_white, Rgba::TRANSPARENT], 3, 1);
+        assert_eq!(
+            pixels(&framebuffer),
+            [[half_white.blend_over(red), red, red]]
+        );
+    }
+
+    #[test]
+    fn draw_text_draws_the_glyph_over_its_background() {
+        let framebuffer = framebuffer(8, 9);
+        framebuffer.fill(Rgba::BLACK);
+        let (lit, unlit) = (Rgba::WHITE, Rgba::rgb(0, 0, 0xff));
+        assert_eq!(framebuffer.draw_text(1, 1, "A", lit, Some(unlit)), 7);
+
+        let columns = glyph('A');
+        for (y, row) in pixels(&framebuffer).iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let (column, glyph_row) = (x as i32 - 1, y as i32 - 1);
+                let expected = if !(0..GLYPH_ADVANCE as i32).contains(&column)
+                    || !(0..GLYPH_HEIGHT as i32).contains(&glyph_row)
+                {
+                    Rgba::BLACK
+                } else if columns
+                    .get(column as usize)
+                    .is_some_and(|bits| bits & (1 << glyph_row) != 0)
+                {
+                    lit
+                } else {
+                    unlit
+                };
+                assert_eq!(pixel, expected, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn draw_text_without_a_background_keeps_the_unlit_pixels() {
+        let framebuffer = framebuffer(6, 7);
+        let red = Rgba::rgb(0xff, 0, 0);
+        framebuffer.fill(red);
+        framebuffer.draw_text(0, 0, "-", Rgba::WHITE, None);
+        // '-' is the middle row of the first five columns.
+        let mut expected = vec![vec![red; 6]; 7];
+        expected[3][..5].fill(Rgba::WHITE);
+        assert_eq!(pixels(&framebuffer), expected);
+    }
+}
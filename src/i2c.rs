@@ -1,71 +1,700 @@
 // SPDX-License-Identifier: MIT
 // Author: Uri Shaked
 
+use std::cell::{Cell, RefCell};
 use std::ffi::c_void;
+use std::rc::Rc;
 
-use wokwi_chip_ll::{i2cInit, I2CConfig};
+use wokwi_chip_ll::{i2cInit, I2CConfig, I2CDevId};
 
 use crate::pin::Pin;
 
+/// How the device address is interpreted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum I2CAddressMode {
+    /// Standard 7-bit address (0x00 - 0x7f).
+    #[default]
+    SevenBit,
+    /// 10-bit address (0x000 - 0x3ff).
+    ///
+    /// The simulator only decodes 7-bit addresses, so a 10-bit device listens on the 7-bit
+    /// address `0b11110xx`, where `xx` are the two high bits of the address. The low 8 bits of the
+    /// address arrive as the first byte written after that. If it matches, the device
+    /// is selected and `connect_callback` is called with the full 10-bit address; if it returns
+    /// false, the byte is NACKed and the device stays unselected. A read (repeated start with
+    /// `0b11110xx` and the read bit) is only accepted after the device was selected by a write,
+    /// and a stop ends the selection.
+    ///
+    /// Since the simulator only sees the 7-bit address, 10-bit devices with the same two high bits
+    /// (e.g. 0x142 and 0x155) listen on the same 7-bit address and can't share a bus: only one of
+    /// them gets the transactions. The same goes for a 7-bit device on 0x78 - 0x7b.
+    TenBit,
+}
+
+impl I2CAddressMode {
+    pub fn max_address(self) -> u32 {
+        match self {
+            I2CAddressMode::SevenBit => 0x7f,
+            I2CAddressMode::TenBit => 0x3ff,
+        }
+    }
+
+    /// Checks that `address` fits in this address mode.
+    ///
+    /// ```rust
+    /// use wokwi_chips_api::i2c::{I2CAddressError, I2CAddressMode};
+    ///
+    /// assert_eq!(I2CAddressMode::SevenBit.validate(0x42), Ok(()));
+    /// assert_eq!(I2CAddressMode::TenBit.validate(0x142), Ok(()));
+    /// assert_eq!(
+    ///     I2CAddressMode::SevenBit.validate(0x142),
+    ///     Err(I2CAddressError {
+    ///         address: 0x142,
+    ///         address_mode: I2CAddressMode::SevenBit,
+    ///     })
+    /// );
+    /// ```
+    pub fn validate(self, address: u32) -> Result<(), I2CAddressError> {
+        if address <= self.max_address() {
+            Ok(())
+        } else {
+            Err(I2CAddressError {
+                address,
+                address_mode: self,
+            })
+        }
+    }
+
+    /// Returns true for the 7-bit addresses the I2C specification reserves for special purposes
+    /// (0x00 - 0x07 and 0x78 - 0x7f). There are no reserved 10-bit addresses.
+    ///
+    /// ```rust
+    /// use wokwi_chips_api::i2c::I2CAddressMode;
+    ///
+    /// assert!(I2CAddressMode::SevenBit.is_reserved(0x00));
+    /// assert!(I2CAddressMode::SevenBit.is_reserved(0x7c));
+    /// assert!(!I2CAddressMode::SevenBit.is_reserved(0x42));
+    /// assert!(!I2CAddressMode::TenBit.is_reserved(0x07));
+    /// ```
+    pub fn is_reserved(self, address: u32) -> bool {
+        match self {
+            I2CAddressMode::SevenBit => address <= 0x07 || (0x78..=0x7f).contains(&address),
+            I2CAddressMode::TenBit => false,
+        }
+    }
+}
+
+/// Error returned when an I2C address doesn't fit in its address mode, e.g. 0x142 with
+/// [`I2CAddressMode::SevenBit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2CAddressError {
+    pub address: u32,
+    pub address_mode: I2CAddressMode,
+}
+
+impl std::fmt::Display for I2CAddressError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "I2C address 0x{:x} is out of range (max 0x{:x})",
+            self.address,
+            self.address_mode.max_address()
+        )
+    }
+}
+
+impl std::error::Error for I2CAddressError {}
+
+/// The response to a byte written by the I2C controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum I2CAck {
+    /// Acknowledge the byte.
+    Ack,
+    /// Reject the byte. The controller usually stops the transaction.
+    Nack,
+}
+
+/// Converts the `bool` returned by older write callbacks, where `true` means ACK.
+impl From<bool> for I2CAck {
+    fn from(ack: bool) -> Self {
+        if ack {
+            I2CAck::Ack
+        } else {
+            I2CAck::Nack
+        }
+    }
+}
+
 pub struct I2CDeviceConfig {
     pub address: u32,
+    pub address_mode: I2CAddressMode,
     pub scl: Pin,
     pub sda: Pin,
 
     pub connect_callback: Option<Box<dyn FnMut(u32, bool) -> bool + 'static>>,
     pub read_callback: Option<Box<dyn FnMut() -> u8 + 'static>>,
-    pub write_callback: Option<Box<dyn FnMut(u8) -> bool + 'static>>,
+    pub write_callback: Option<Box<dyn FnMut(u8) -> I2CAck + 'static>>,
     pub disconnect_callback: Option<Box<dyn FnMut() + 'static>>,
 }
 
-// This is a global registry of all the I2C devices, so that we can keep the Rust callbacks during
-// the chip's lifetime.
-static mut I2C_CONFIG_REGISTRY: Vec<*const I2CDeviceConfig> = Vec::new();
+impl I2CDeviceConfig {
+    /// Creates a 7-bit address config without any callbacks. The address and pins must always be
+    /// provided; set the callbacks you need with struct update syntax.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::i2c::{I2CDeviceConfig, create};
+    ///
+    /// let scl = Pin::new("SCL", PinMode::Input);
+    /// let sda = Pin::new("SDA", PinMode::Input);
+    /// create(I2CDeviceConfig {
+    ///     read_callback: Some(Box::new(|| 0x42)),
+    ///     ..I2CDeviceConfig::new(0x42, scl, sda)
+    /// });
+    /// ```
+    pub fn new(address: u32, scl: Pin, sda: Pin) -> Self {
+        Self {
+            address,
+            address_mode: I2CAddressMode::SevenBit,
+            scl,
+            sda,
+            connect_callback: None,
+            read_callback: None,
+            write_callback: None,
+            disconnect_callback: None,
+        }
+    }
 
-extern "C" fn i2c_connect_trampoline(user_data: *mut c_void, address: u32, write: bool) -> bool {
-    let i2c_device = unsafe { &mut *(user_data as *mut I2CDeviceConfig) };
-    if let Some(callback) = &mut i2c_device.connect_callback {
-        callback(address, write)
-    } else {
-        false
+    /// Returns a builder for a device config, where the callbacks that aren't set are `None`.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::i2c::{I2CAck, I2CDeviceConfig, create};
+    ///
+    /// let scl = Pin::new("SCL", PinMode::Input);
+    /// let sda = Pin::new("SDA", PinMode::Input);
+    /// create(
+    ///     I2CDeviceConfig::builder(0x42, scl, sda)
+    ///         .on_write(|data| {
+    ///             println!("I2C write: 0x{:02x}", data);
+    ///             I2CAck::Ack
+    ///         })
+    ///         .build(),
+    /// );
+    /// ```
+    pub fn builder(address: u32, scl: Pin, sda: Pin) -> I2CDeviceConfigBuilder {
+        I2CDeviceConfigBuilder {
+            config: I2CDeviceConfig::new(address, scl, sda),
+        }
+    }
+
+    /// Wraps the callbacks so that every connect, byte read/written and disconnect is printed to
+    /// the debug console, starting with `prefix`. The original callbacks are still called.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::i2c::{create, I2CDeviceConfig};
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// let scl = Pin::new("SCL", PinMode::Input);
+    /// let sda = Pin::new("SDA", PinMode::Input);
+    /// create(
+    ///     I2CDeviceConfig::builder(0x42, scl, sda)
+    ///         .on_read(|| 0x42)
+    ///         .build()
+    ///         .logged("[sensor]"),
+    /// );
+    /// ```
+    pub fn logged(mut self, prefix: &str) -> Self {
+        let prefix: Rc<str> = Rc::from(prefix);
+
+        let mut connect = self.connect_callback.take();
+        let connect_prefix = prefix.clone();
+        self.connect_callback = Some(Box::new(move |address, write| {
+            let ack = connect
+                .as_mut()
+                .is_some_and(|callback| callback(address, write));
+            crate::println!(
+                "{} connect: address=0x{:02x} {} {}",
+                connect_prefix,
+                address,
+                if write { "W" } else { "R" },
+                if ack { "ACK" } else { "NACK" }
+            );
+            ack
+        }));
+
+        let mut read = self.read_callback.take();
+        let read_prefix = prefix.clone();
+        self.read_callback = Some(Box::new(move || {
+            let data = read.as_mut().map_or(0, |callback| callback());
+            crate::println!("{} read: 0x{:02x}", read_prefix, data);
+            data
+        }));
+
+        let mut write = self.write_callback.take();
+        let write_prefix = prefix.clone();
+        self.write_callback = Some(Box::new(move |data| {
+            let ack = write
+                .as_mut()
+                .map_or(I2CAck::Nack, |callback| callback(data));
+            crate::println!("{} write: 0x{:02x} {:?}", write_prefix, data, ack);
+            ack
+        }));
+
+        let mut disconnect = self.disconnect_callback.take();
+        self.disconnect_callback = Some(Box::new(move || {
+            crate::println!("{} disconnect", prefix);
+            if let Some(callback) = disconnect.as_mut() {
+                callback();
+            }
+        }));
+
+        self
     }
 }
 
-extern "C" fn i2c_read_trampoline(user_data: *mut c_void) -> u8 {
-    let i2c_device = unsafe { &mut *(user_data as *mut I2CDeviceConfig) };
-    if i2c_device.read_callback.is_some() {
-        i2c_device.read_callback.as_mut().unwrap()()
-    } else {
-        0
+/// Builder for [`I2CDeviceConfig`], see [`I2CDeviceConfig::builder`].
+pub struct I2CDeviceConfigBuilder {
+    config: I2CDeviceConfig,
+}
+
+impl I2CDeviceConfigBuilder {
+    pub fn address_mode(mut self, address_mode: I2CAddressMode) -> Self {
+        self.config.address_mode = address_mode;
+        self
+    }
+
+    pub fn on_connect<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(u32, bool) -> bool + 'static,
+    {
+        self.config.connect_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_read<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut() -> u8 + 'static,
+    {
+        self.config.read_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the write callback. It can return either an [`I2CAck`] or a `bool`, where `true`
+    /// means ACK.
+    pub fn on_write<F, R>(mut self, mut callback: F) -> Self
+    where
+        F: FnMut(u8) -> R + 'static,
+        R: Into<I2CAck>,
+    {
+        self.config.write_callback = Some(Box::new(move |data| callback(data).into()));
+        self
+    }
+
+    pub fn on_disconnect<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut() + 'static,
+    {
+        self.config.disconnect_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn build(self) -> I2CDeviceConfig {
+        self.config
     }
 }
 
-extern "C" fn i2c_write_trampoline(user_data: *mut c_void, data: u8) {
-    let i2c_device = unsafe { &mut *(user_data as *mut I2CDeviceConfig) };
-    if i2c_device.write_callback.is_some() {
-        i2c_device.write_callback.as_mut().unwrap()(data);
+/// What [`I2CReadBuffer`] returns once all of its bytes have been read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadBufferEnd {
+    /// Start again from the first byte.
+    Wrap,
+    /// Return the given byte, e.g. 0xff like an idle bus.
+    Fill(u8),
+}
+
+struct ReadBufferState {
+    data: Vec<u8>,
+    position: usize,
+}
+
+/// A fixed response that is returned byte by byte from a read callback.
+///
+/// The buffer can be cloned; clones share the same data, so the response can be updated from
+/// other callbacks.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::i2c::{create, I2CDeviceConfig, I2CReadBuffer, ReadBufferEnd};
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let response = I2CReadBuffer::new(vec![1, 2, 3, 4, 5, 6], ReadBufferEnd::Fill(0xff));
+/// let scl = Pin::new("SCL", PinMode::Input);
+/// let sda = Pin::new("SDA", PinMode::Input);
+/// let rewind = response.clone();
+/// create(
+///     I2CDeviceConfig::builder(0x68, scl, sda)
+///         .on_connect(move |_, _| {
+///             rewind.rewind();
+///             true
+///         })
+///         .on_read(response.reader())
+///         .build(),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct I2CReadBuffer {
+    state: Rc<RefCell<ReadBufferState>>,
+    end: ReadBufferEnd,
+}
+
+impl I2CReadBuffer {
+    pub fn new(data: Vec<u8>, end: ReadBufferEnd) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(ReadBufferState { data, position: 0 })),
+            end,
+        }
+    }
+
+    /// Replaces the response, and starts again from its first byte.
+    pub fn set_data(&self, data: Vec<u8>) {
+        *self.state.borrow_mut() = ReadBufferState { data, position: 0 };
+    }
+
+    /// Starts again from the first byte.
+    pub fn rewind(&self) {
+        self.state.borrow_mut().position = 0;
+    }
+
+    /// Returns the next byte of the response.
+    ///
+    /// ```rust
+    /// use wokwi_chips_api::i2c::{I2CReadBuffer, ReadBufferEnd};
+    ///
+    /// let buffer = I2CReadBuffer::new(vec![1, 2], ReadBufferEnd::Wrap);
+    /// let bytes: Vec<u8> = (0..3).map(|_| buffer.next_byte()).collect();
+    /// assert_eq!(bytes, [1, 2, 1]);
+    ///
+    /// let buffer = I2CReadBuffer::new(vec![1, 2], ReadBufferEnd::Fill(0xff));
+    /// let bytes: Vec<u8> = (0..3).map(|_| buffer.next_byte()).collect();
+    /// assert_eq!(bytes, [1, 2, 0xff]);
+    /// ```
+    pub fn next_byte(&self) -> u8 {
+        let mut state = self.state.borrow_mut();
+        if state.position >= state.data.len() {
+            match self.end {
+                ReadBufferEnd::Fill(value) => return value,
+                ReadBufferEnd::Wrap if state.data.is_empty() => return 0xff,
+                ReadBufferEnd::Wrap => state.position = 0,
+            }
+        }
+        let value = state.data[state.position];
+        state.position += 1;
+        value
+    }
+
+    /// Returns a read callback for [`I2CDeviceConfig`] that reads from this buffer.
+    pub fn reader(&self) -> impl FnMut() -> u8 + 'static {
+        let buffer = self.clone();
+        move || buffer.next_byte()
     }
 }
 
+// I2C callbacks only receive the `user_data` pointer, so each device gets a registry key that is
+// passed as `user_data` and used to look up its config.
+type I2CKey = usize;
+
+struct I2CListener {
+    key: I2CKey,
+    // Taken out while a callback runs, so that the callback is free to access the registry.
+    config: Option<Box<I2CDeviceConfig>>,
+}
+
+thread_local! {
+    // This is a global registry of all the I2C devices, so that we can keep the Rust callbacks
+    // during the device's lifetime.
+    static I2C_CONFIG_REGISTRY: RefCell<Vec<I2CListener>> = const { RefCell::new(Vec::new()) };
+    static NEXT_I2C_KEY: Cell<I2CKey> = const { Cell::new(0) };
+}
+
+fn register_device(config: I2CDeviceConfig) -> I2CKey {
+    let key = NEXT_I2C_KEY.get();
+    NEXT_I2C_KEY.set(key + 1);
+    I2C_CONFIG_REGISTRY.with_borrow_mut(|registry| {
+        registry.push(I2CListener {
+            key,
+            config: Some(Box::new(config)),
+        });
+    });
+    key
+}
+
+fn unregister_device(key: I2CKey) {
+    I2C_CONFIG_REGISTRY.with_borrow_mut(|registry| registry.retain(|listener| listener.key != key));
+}
+
+/// Runs `f` with the config of the device identified by `user_data`, or returns `default` if the
+/// device is gone.
+fn with_device<R>(
+    user_data: *mut c_void,
+    default: R,
+    f: impl FnOnce(&mut I2CDeviceConfig) -> R,
+) -> R {
+    let key = user_data as I2CKey;
+    let config = I2C_CONFIG_REGISTRY.with_borrow_mut(|registry| {
+        registry
+            .iter_mut()
+            .find(|listener| listener.key == key)
+            .and_then(|listener| listener.config.take())
+    });
+    let Some(mut config) = config else {
+        return default;
+    };
+
+    let result = f(&mut config);
+
+    I2C_CONFIG_REGISTRY.with_borrow_mut(|registry| {
+        // If the device was dropped while the callback was running, the config is dropped here.
+        if let Some(listener) = registry.iter_mut().find(|listener| listener.key == key) {
+            listener.config = Some(config);
+        }
+    });
+    result
+}
+
+extern "C" fn i2c_connect_trampoline(user_data: *mut c_void, address: u32, write: bool) -> bool {
+    with_device(user_data, false, |i2c_device| {
+        if let Some(callback) = &mut i2c_device.connect_callback {
+            callback(address, write)
+        } else {
+            false
+        }
+    })
+}
+
+extern "C" fn i2c_read_trampoline(user_data: *mut c_void) -> u8 {
+    with_device(user_data, 0, |i2c_device| {
+        if let Some(callback) = &mut i2c_device.read_callback {
+            callback()
+        } else {
+            0
+        }
+    })
+}
+
+extern "C" fn i2c_write_trampoline(user_data: *mut c_void, data: u8) -> bool {
+    with_device(user_data, false, |i2c_device| {
+        if let Some(callback) = &mut i2c_device.write_callback {
+            callback(data) == I2CAck::Ack
+        } else {
+            false
+        }
+    })
+}
+
 extern "C" fn i2c_disconnect_trampoline(user_data: *mut c_void) {
-    let i2c_device = unsafe { &mut *(user_data as *mut I2CDeviceConfig) };
-    if i2c_device.disconnect_callback.is_some() {
-        i2c_device.disconnect_callback.as_mut().unwrap()();
+    with_device(user_data, (), |i2c_device| {
+        if let Some(callback) = &mut i2c_device.disconnect_callback {
+            callback();
+        }
+    })
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum TenBitState {
+    Idle,
+    AwaitingLowByte,
+    Selected,
+}
+
+/// Wraps the callbacks of a 10-bit device with the matching of the second address byte. Returns
+/// the config along with the 7-bit address that the simulator should listen on.
+fn ten_bit_config(mut config: I2CDeviceConfig) -> (I2CDeviceConfig, u32) {
+    let address = config.address;
+    let state = Rc::new(Cell::new(TenBitState::Idle));
+
+    // The user's connect callback is called from the write callback too, once the low address
+    // byte matches.
+    let connect = Rc::new(RefCell::new(config.connect_callback.take()));
+    let write_connect = connect.clone();
+    let connect_state = state.clone();
+    config.connect_callback = Some(Box::new(move |_, is_write| {
+        if is_write {
+            // The low address byte comes next
+            connect_state.set(TenBitState::AwaitingLowByte);
+            return true;
+        }
+        if connect_state.get() != TenBitState::Selected {
+            return false;
+        }
+        let mut connect = connect.borrow_mut();
+        connect
+            .as_mut()
+            .is_some_and(|callback| callback(address, false))
+    }));
+
+    let mut write = config.write_callback.take();
+    let write_state = state.clone();
+    config.write_callback = Some(Box::new(move |data| match write_state.get() {
+        TenBitState::AwaitingLowByte if data as u32 == address & 0xff => {
+            let mut connect = write_connect.borrow_mut();
+            let ack = connect
+                .as_mut()
+                .is_some_and(|callback| callback(address, true));
+            // A NACKed connect leaves the device unselected, so the rest of the transaction
+            // doesn't reach its callbacks.
+            write_state.set(if ack {
+                TenBitState::Selected
+            } else {
+                TenBitState::Idle
+            });
+            ack.into()
+        }
+        TenBitState::Selected => write
+            .as_mut()
+            .map_or(I2CAck::Nack, |callback| callback(data)),
+        _ => {
+            write_state.set(TenBitState::Idle);
+            I2CAck::Nack
+        }
+    }));
+
+    let mut read = config.read_callback.take();
+    let read_state = state.clone();
+    config.read_callback = Some(Box::new(move || match read_state.get() {
+        TenBitState::Selected => read.as_mut().map_or(0xff, |callback| callback()),
+        _ => 0xff,
+    }));
+
+    let mut disconnect = config.disconnect_callback.take();
+    config.disconnect_callback = Some(Box::new(move || {
+        // A stop ends the selection: the next transaction has to send the low address byte again.
+        if state.replace(TenBitState::Idle) == TenBitState::Selected {
+            if let Some(callback) = disconnect.as_mut() {
+                callback();
+            }
+        }
+    }));
+
+    (config, 0b1111000 | (address >> 8))
+}
+
+/// An I2C device on the bus.
+///
+/// The simulator can't remove a device from the bus, so after the `I2CDevice` is dropped, the
+/// device stays on the bus but no longer calls any callbacks, and NACKs every transaction.
+pub struct I2CDevice {
+    id: I2CDevId,
+    key: I2CKey,
+}
+
+impl I2CDevice {
+    /// Creates a new I2C device, that lives as long as the returned handle. See [`create`] for an
+    /// example.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address is out of range for the address mode. Use [`I2CDevice::try_new`] to
+    /// handle the error instead.
+    pub fn new(config: I2CDeviceConfig) -> Self {
+        Self::try_new(config).unwrap()
+    }
+
+    /// Creates a new I2C device, or returns an error if the address is out of range for the
+    /// address mode. Reserved addresses are accepted, but print a warning.
+    pub fn try_new(config: I2CDeviceConfig) -> Result<Self, I2CAddressError> {
+        config.address_mode.validate(config.address)?;
+        if config.address_mode.is_reserved(config.address) {
+            crate::println!(
+                "warning: I2C address 0x{:02x} is reserved by the I2C specification",
+                config.address
+            );
+        }
+        let (config, address) = match config.address_mode {
+            I2CAddressMode::SevenBit => {
+                let address = config.address;
+                (config, address)
+            }
+            I2CAddressMode::TenBit => ten_bit_config(config),
+        };
+        let scl = config.scl.get_id();
+        let sda = config.sda.get_id();
+        let key = register_device(config);
+        let ll_config = I2CConfig {
+            user_data: key as *const c_void,
+            address,
+            scl,
+            sda,
+            connect: i2c_connect_trampoline as *const c_void,
+            read: i2c_read_trampoline as *const c_void,
+            write: i2c_write_trampoline as *const c_void,
+            disconnect: i2c_disconnect_trampoline as *const c_void,
+        };
+        let id = unsafe { i2cInit(&ll_config) };
+
+        Ok(Self { id, key })
+    }
+
+    pub fn get_id(&self) -> I2CDevId {
+        self.id
+    }
+
+    /// Replaces the callbacks of the device with the ones `f` sets on the config. The config of a
+    /// 10-bit device is wrapped again, so the new callbacks only see the device's transactions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from inside one of the device's callbacks, since the config is taken out
+    /// of the registry while they run.
+    pub(crate) fn replace_callbacks(&self, f: impl FnOnce(&mut I2CDeviceConfig)) {
+        I2C_CONFIG_REGISTRY.with_borrow_mut(|registry| {
+            let listener = registry
+                .iter_mut()
+                .find(|listener| listener.key == self.key)
+                .expect("I2C device is registered");
+            let mut config = *listener
+                .config
+                .take()
+                .expect("can't replace the callbacks of an I2C device from its own callback");
+            f(&mut config);
+            if config.address_mode == I2CAddressMode::TenBit {
+                config = ten_bit_config(config).0;
+            }
+            listener.config = Some(Box::new(config));
+        });
     }
 }
 
-/// Create a new I2C device.
+impl Drop for I2CDevice {
+    fn drop(&mut self) {
+        unregister_device(self.key);
+    }
+}
+
+/// Create a new I2C device that stays on the bus for the whole lifetime of the chip. Use
+/// [`I2CDevice::new`] to get a handle that can disable the device.
+///
+/// If the address is out of range for the address mode, the device isn't created, and an error
+/// is printed to the debug console.
 ///
 /// Example:
 ///
-/// ```rust
+/// ```rust,no_run
 /// use wokwi_chips_api::pin::{Pin, PinMode};
-/// use wokwi_chips_api::i2c::{I2CDeviceConfig, create};
+/// use wokwi_chips_api::i2c::{I2CAck, I2CAddressMode, I2CDeviceConfig, create};
 ///
 /// let scl = Pin::new("SCL", PinMode::Output);
 /// let sda = Pin::new("SDA", PinMode::Output);
 /// create(I2CDeviceConfig {
 ///     address: 0x42,
+///     address_mode: I2CAddressMode::SevenBit,
 ///     scl,
 ///     sda,
 ///     connect_callback: Some(Box::new(|address, write| {
@@ -78,7 +707,7 @@ extern "C" fn i2c_disconnect_trampoline(user_data: *mut c_void) {
 ///     })),
 ///     write_callback: Some(Box::new(|data| {
 ///         println!("I2C write: 0x{:02x}", data);
-///         true // ACK, false for NACK
+///         I2CAck::Ack
 ///     })),
 ///     disconnect_callback: Some(Box::new(|| {
 ///         println!("I2C disconnect");
@@ -87,20 +716,173 @@ extern "C" fn i2c_disconnect_trampoline(user_data: *mut c_void) {
 /// ```
 ///
 pub fn create(config: I2CDeviceConfig) {
-    let ll_config = I2CConfig {
-        user_data: &config as *const _ as *const c_void,
-        address: config.address,
-        scl: config.scl.get_id(),
-        sda: config.sda.get_id(),
-        connect: i2c_connect_trampoline as *const c_void,
-        read: i2c_read_trampoline as *const c_void,
-        write: i2c_write_trampoline as *const c_void,
-        disconnect: i2c_disconnect_trampoline as *const c_void,
-    };
-    unsafe {
-        i2cInit(&ll_config);
+    match I2CDevice::try_new(config) {
+        // Keep the device for the whole lifetime of the chip.
+        Ok(device) => std::mem::forget(device),
+        Err(err) => crate::println!("error: {}", err),
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{self, VirtualI2cBus};
+    use crate::pin::PinMode;
+
+    fn pins() -> (Pin, Pin) {
+        (
+            Pin::new("SCL", PinMode::Input),
+            Pin::new("SDA", PinMode::Input),
+        )
+    }
+
+    // A 10-bit device that ACKs its connect if `accept` is true, and records the bytes written.
+    fn ten_bit_device(address: u32, accept: bool) -> (I2CDevice, Rc<RefCell<Vec<u8>>>) {
+        let (scl, sda) = pins();
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let write_written = written.clone();
+        let device = I2CDevice::new(
+            I2CDeviceConfig::builder(address, scl, sda)
+                .address_mode(I2CAddressMode::TenBit)
+                .on_connect(move |_, _| accept)
+                .on_read(|| 0x5a)
+                .on_write(move |data| {
+                    write_written.borrow_mut().push(data);
+                    I2CAck::Ack
+                })
+                .build(),
+        );
+        (device, written)
     }
-    unsafe {
-        I2C_CONFIG_REGISTRY.push(&config);
+
+    #[test]
+    fn seven_bit_device_responds_to_its_own_address() {
+        let (scl, sda) = pins();
+        let _device = I2CDevice::new(
+            I2CDeviceConfig::builder(0x42, scl, sda)
+                .on_connect(|address, _| address == 0x42)
+                .on_read(|| 0x5a)
+                .build(),
+        );
+        let mut bus = VirtualI2cBus::new();
+        assert!(!bus.start(0x43, false));
+        assert!(bus.start(0x42, false));
+        assert_eq!(bus.read(), 0x5a);
+        bus.stop();
+    }
+
+    #[test]
+    fn created_device_keeps_responding() {
+        let (scl, sda) = pins();
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let write_written = written.clone();
+        create(
+            I2CDeviceConfig::builder(0x42, scl, sda)
+                .on_connect(|_, _| true)
+                .on_write(move |data| {
+                    write_written.borrow_mut().push(data);
+                    I2CAck::Ack
+                })
+                .build(),
+        );
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x42, true));
+        assert!(bus.write(0x10));
+        assert!(bus.write(0x20));
+        bus.stop();
+        assert_eq!(*written.borrow(), [0x10, 0x20]);
+    }
+
+    #[test]
+    fn out_of_range_address_is_rejected() {
+        let (scl, sda) = pins();
+        let result = I2CDevice::try_new(I2CDeviceConfig::new(0x142, scl, sda));
+        assert_eq!(
+            result.err(),
+            Some(I2CAddressError {
+                address: 0x142,
+                address_mode: I2CAddressMode::SevenBit,
+            })
+        );
+
+        let (scl, sda) = pins();
+        create(I2CDeviceConfig::new(0x142, scl, sda));
+        assert!(mock::i2c_addresses().is_empty());
+        assert_eq!(
+            mock::take_output(),
+            "error: I2C address 0x142 is out of range (max 0x7f)\n"
+        );
+    }
+
+    #[test]
+    fn reserved_address_is_accepted_with_a_warning() {
+        let (scl, sda) = pins();
+        let _device = I2CDevice::new(I2CDeviceConfig::new(0x03, scl, sda));
+        assert_eq!(mock::i2c_addresses(), [0x03]);
+        assert!(mock::take_output().starts_with("warning: I2C address 0x03 is reserved"));
+    }
+
+    #[test]
+    fn write_callback_nack_reaches_the_controller() {
+        let (scl, sda) = pins();
+        let _device = I2CDevice::new(
+            I2CDeviceConfig::builder(0x42, scl, sda)
+                .on_connect(|_, _| true)
+                .on_write(|data| {
+                    if data < 0x80 {
+                        I2CAck::Ack
+                    } else {
+                        I2CAck::Nack
+                    }
+                })
+                .build(),
+        );
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x42, true));
+        assert!(bus.write(0x10));
+        assert!(!bus.write(0x90));
+        bus.stop();
+    }
+
+    #[test]
+    fn ten_bit_device_responds_to_its_own_address() {
+        let (_device, written) = ten_bit_device(0x142, true);
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0b1111001, true));
+        assert!(bus.write(0x42));
+        assert!(bus.write(0x10));
+        assert!(bus.start(0b1111001, false));
+        assert_eq!(bus.read(), 0x5a);
+        bus.stop();
+        assert_eq!(*written.borrow(), [0x10]);
+
+        // Another low address byte
+        assert!(bus.start(0b1111001, true));
+        assert!(!bus.write(0x43));
+        assert!(!bus.write(0x10));
+        bus.stop();
+        assert_eq!(*written.borrow(), [0x10]);
+    }
+
+    #[test]
+    fn ten_bit_nacked_connect_leaves_the_device_unselected() {
+        let (_device, written) = ten_bit_device(0x142, false);
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0b1111001, true));
+        assert!(!bus.write(0x42));
+        assert!(!bus.write(0x10));
+        assert!(!bus.start(0b1111001, false));
+        assert!(written.borrow().is_empty());
+    }
+
+    #[test]
+    fn ten_bit_stop_ends_the_selection() {
+        let (_device, _) = ten_bit_device(0x142, true);
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0b1111001, true));
+        assert!(bus.write(0x42));
+        bus.stop();
+        // A read without selecting the device again
+        assert!(!bus.start(0b1111001, false));
     }
 }
@@ -0,0 +1,362 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! An async view of an I2C device, see [`I2CDevice::transactions`].
+//!
+//! The simulator calls the I2C callbacks one byte at a time, and each callback has to answer
+//! right away: the LL API can't stretch the clock while the chip prepares a byte. The bridge
+//! works because of how the [`executor`](crate::executor) runs tasks: a task woken from an I2C
+//! callback runs before the callback returns. So a task that answers without waiting for
+//! anything else (e.g. a timer) answers in time:
+//!
+//! - A connect is always ACKed, and the task awaiting [`I2CTransactions::accept`] runs.
+//! - A byte written by the controller is always ACKed, and the task awaiting
+//!   [`I2CTransaction::read_byte`] runs.
+//! - When the controller reads a byte, it gets the oldest byte queued by
+//!   [`I2CTransaction::write_byte`], and the task waiting for that byte to be sent runs, so it can
+//!   queue the next one. If no byte is queued, the controller reads `0xff`, like an idle bus.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use crate::i2c::{I2CAck, I2CDevice};
+
+// A task waiting for an event, woken when it comes.
+#[derive(Default)]
+struct WakerSlot(RefCell<Option<Waker>>);
+
+impl WakerSlot {
+    fn register(&self, cx: &Context<'_>) {
+        let mut waker = self.0.borrow_mut();
+        match waker.as_mut() {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *waker = Some(cx.waker().clone()),
+        }
+    }
+
+    fn wake(&self) {
+        // Taken out first: the woken task runs right away, and may register again.
+        let waker = self.0.take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+// Shared with the device callbacks.
+#[derive(Default)]
+struct Bridge {
+    // Transactions that started but weren't accepted yet.
+    pending: RefCell<VecDeque<Rc<TransactionState>>>,
+    current: RefCell<Option<Rc<TransactionState>>>,
+    waker: WakerSlot,
+}
+
+struct TransactionState {
+    address: u32,
+    write: bool,
+    // Bytes written by the controller, until the task reads them.
+    received: RefCell<VecDeque<u8>>,
+    // Bytes queued by the task, until the controller reads them.
+    to_send: RefCell<VecDeque<u8>>,
+    // The number of bytes queued so far, and the number of them the controller read.
+    queued: Cell<usize>,
+    sent: Cell<usize>,
+    ended: Cell<bool>,
+    waker: WakerSlot,
+}
+
+impl Bridge {
+    fn current(&self) -> Option<Rc<TransactionState>> {
+        self.current.borrow().clone()
+    }
+
+    fn end_current(&self) {
+        let current = self.current.take();
+        if let Some(transaction) = current {
+            transaction.ended.set(true);
+            transaction.waker.wake();
+        }
+    }
+
+    fn connect(&self, address: u32, write: bool) -> bool {
+        // A repeated start ends the previous transaction without a disconnect.
+        self.end_current();
+        let transaction = Rc::new(TransactionState {
+            address,
+            write,
+            received: RefCell::default(),
+            to_send: RefCell::default(),
+            queued: Cell::new(0),
+            sent: Cell::new(0),
+            ended: Cell::new(false),
+            waker: WakerSlot::default(),
+        });
+        self.current.replace(Some(transaction.clone()));
+        self.pending.borrow_mut().push_back(transaction);
+        self.waker.wake();
+        true
+    }
+
+    fn read(&self) -> u8 {
+        let Some(transaction) = self.current() else {
+            return 0xff;
+        };
+        let data = transaction.to_send.borrow_mut().pop_front();
+        let Some(data) = data else {
+            return 0xff;
+        };
+        transaction.sent.set(transaction.sent.get() + 1);
+        transaction.waker.wake();
+        data
+    }
+
+    fn write(&self, data: u8) -> I2CAck {
+        let Some(transaction) = self.current() else {
+            return I2CAck::Nack;
+        };
+        transaction.received.borrow_mut().push_back(data);
+        transaction.waker.wake();
+        I2CAck::Ack
+    }
+}
+
+impl I2CDevice {
+    /// Replaces the callbacks of the device with an async view of its transactions, for chips
+    /// whose protocol reads better as sequential code than as separate callbacks. See the
+    /// [`i2c_async`](crate::i2c_async) module for how this maps to the simulator's callbacks.
+    ///
+    /// Example, a device with a register pointer, which the controller sets with a write, and
+    /// then reads the register from with a (repeated start) read:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::executor;
+    /// use wokwi_chips_api::i2c::{I2CDevice, I2CDeviceConfig};
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// let scl = Pin::new("SCL", PinMode::Input);
+    /// let sda = Pin::new("SDA", PinMode::Input);
+    /// let device = I2CDevice::new(I2CDeviceConfig::new(0x48, scl, sda));
+    /// let mut transactions = device.transactions();
+    /// executor::spawn(async move {
+    ///     let registers = [0x12, 0x34, 0x56, 0x78];
+    ///     let mut pointer = 0;
+    ///     loop {
+    ///         let transaction = transactions.accept().await;
+    ///         if transaction.is_write() {
+    ///             if let Some(register) = transaction.read_byte().await {
+    ///                 pointer = register as usize % registers.len();
+    ///             }
+    ///         } else {
+    ///             while transaction.write_byte(registers[pointer]).await {
+    ///                 pointer = (pointer + 1) % registers.len();
+    ///             }
+    ///         }
+    ///     }
+    /// });
+    /// executor::run();
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from inside one of the device's callbacks.
+    pub fn transactions(&self) -> I2CTransactions {
+        let bridge = Rc::new(Bridge::default());
+        self.replace_callbacks(|config| {
+            let connect = bridge.clone();
+            config.connect_callback = Some(Box::new(move |address, write| {
+                connect.connect(address, write)
+            }));
+            let read = bridge.clone();
+            config.read_callback = Some(Box::new(move || read.read()));
+            let write = bridge.clone();
+            config.write_callback = Some(Box::new(move |data| write.write(data)));
+            let disconnect = bridge.clone();
+            config.disconnect_callback = Some(Box::new(move || disconnect.end_current()));
+        });
+        I2CTransactions { bridge }
+    }
+}
+
+/// The transactions of an I2C device, see [`I2CDevice::transactions`].
+pub struct I2CTransactions {
+    bridge: Rc<Bridge>,
+}
+
+impl I2CTransactions {
+    /// Returns a future that resolves to the next transaction, once the controller addresses the
+    /// device. Transactions that started before are returned first, in order.
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { transactions: self }
+    }
+}
+
+/// A future that resolves to the next transaction, see [`I2CTransactions::accept`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct Accept<'a> {
+    transactions: &'a mut I2CTransactions,
+}
+
+impl Future for Accept<'_> {
+    type Output = I2CTransaction;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<I2CTransaction> {
+        let bridge = &self.transactions.bridge;
+        if let Some(state) = bridge.pending.borrow_mut().pop_front() {
+            return Poll::Ready(I2CTransaction { state });
+        }
+        bridge.waker.register(cx);
+        Poll::Pending
+    }
+}
+
+/// A single I2C transaction, from the controller addressing the device until it stops or
+/// restarts. Only one task should wait on a transaction at a time.
+pub struct I2CTransaction {
+    state: Rc<TransactionState>,
+}
+
+impl I2CTransaction {
+    /// The address the controller sent, which is the device address.
+    pub fn address(&self) -> u32 {
+        self.state.address
+    }
+
+    /// Returns true if the controller writes to the device in this transaction, false if it
+    /// reads.
+    pub fn is_write(&self) -> bool {
+        self.state.write
+    }
+
+    /// Returns true once the controller stopped or restarted the transaction.
+    pub fn is_finished(&self) -> bool {
+        self.state.ended.get()
+    }
+
+    /// Returns a future that resolves to the next byte written by the controller, or `None` once
+    /// the transaction is over and all the bytes were read.
+    pub fn read_byte(&self) -> ReadByte<'_> {
+        ReadByte { transaction: self }
+    }
+
+    /// Queues a byte for the controller to read, and returns a future that resolves to true once
+    /// the controller read it, or false if the transaction ended first.
+    ///
+    /// The byte is queued when this is called, so several bytes can be queued before awaiting
+    /// any of them.
+    pub fn write_byte(&self, data: u8) -> WriteByte<'_> {
+        let index = self.state.queued.get();
+        if !self.state.ended.get() {
+            self.state.to_send.borrow_mut().push_back(data);
+            self.state.queued.set(index + 1);
+        }
+        WriteByte {
+            transaction: self,
+            index,
+        }
+    }
+}
+
+/// A future that resolves to the next byte written by the controller, see
+/// [`I2CTransaction::read_byte`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct ReadByte<'a> {
+    transaction: &'a I2CTransaction,
+}
+
+impl Future for ReadByte<'_> {
+    type Output = Option<u8>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u8>> {
+        let state = &self.transaction.state;
+        if let Some(data) = state.received.borrow_mut().pop_front() {
+            return Poll::Ready(Some(data));
+        }
+        if state.ended.get() {
+            return Poll::Ready(None);
+        }
+        state.waker.register(cx);
+        Poll::Pending
+    }
+}
+
+/// A future that resolves once the controller read a queued byte, see
+/// [`I2CTransaction::write_byte`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct WriteByte<'a> {
+    transaction: &'a I2CTransaction,
+    index: usize,
+}
+
+impl Future for WriteByte<'_> {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let state = &self.transaction.state;
+        if state.sent.get() > self.index {
+            return Poll::Ready(true);
+        }
+        if state.ended.get() {
+            return Poll::Ready(false);
+        }
+        state.waker.register(cx);
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::executor;
+    use crate::i2c::I2CDeviceConfig;
+    use crate::mock::VirtualI2cBus;
+    use crate::pin::{Pin as ChipPin, PinMode};
+
+    #[test]
+    fn a_task_answers_a_read_after_write() {
+        let scl = ChipPin::new("SCL", PinMode::Input);
+        let sda = ChipPin::new("SDA", PinMode::Input);
+        let device = I2CDevice::new(I2CDeviceConfig::new(0x48, scl, sda));
+        let mut transactions = device.transactions();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let task_received = received.clone();
+        executor::spawn(async move {
+            let registers = [0x12, 0x34, 0x56, 0x78];
+            let write = transactions.accept().await;
+            assert!(write.is_write());
+            let mut pointer = 0;
+            while let Some(register) = write.read_byte().await {
+                task_received.borrow_mut().push(register);
+                pointer = register as usize % registers.len();
+            }
+
+            let read = transactions.accept().await;
+            assert!(!read.is_write());
+            while read.write_byte(registers[pointer]).await {
+                pointer = (pointer + 1) % registers.len();
+            }
+            assert!(read.is_finished());
+        });
+        executor::run();
+
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x48, true));
+        assert!(bus.write(0x02));
+        assert_eq!(*received.borrow(), [0x02]);
+        // A repeated start ends the write.
+        assert!(bus.start(0x48, false));
+        assert_eq!(bus.read(), 0x56);
+        assert_eq!(bus.read(), 0x78);
+        assert_eq!(bus.read(), 0x12);
+        bus.stop();
+
+        // The task is done, so nothing answers anymore.
+        assert!(bus.start(0x48, false));
+        assert_eq!(bus.read(), 0xff);
+        bus.stop();
+    }
+}
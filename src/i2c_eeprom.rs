@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use crate::i2c::I2CDevice;
+use crate::i2c_register::{I2CRegisterDevice, RegisterLayout};
+use crate::pin::Pin;
+
+/// A 24Cxx-style I2C EEPROM, such as the 24C32 or 24C256.
+///
+/// Each write transaction starts with a two-byte memory address (most significant byte first),
+/// followed by the data bytes. Writes wrap around within the current page, just like the real
+/// part, so writing past the end of a page overwrites the start of the same page. Reads start at
+/// the last address set and continue through the whole memory, wrapping around at the end.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::i2c_eeprom::I2CEeprom;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let scl = Pin::new("SCL", PinMode::Input);
+/// let sda = Pin::new("SDA", PinMode::Input);
+/// // 24C32: 4 KiB, 32-byte pages
+/// let eeprom = I2CEeprom::new(0x50, scl, sda, 4096, 32);
+/// eeprom.load(b"Hello, Wokwi!");
+///
+/// // Later, e.g. from a timer: inspect what the microcontroller wrote across the page
+/// // boundary at 0x20.
+/// let memory = eeprom.dump();
+/// println!("{:02x?}", &memory[0x1c..0x24]);
+/// ```
+pub struct I2CEeprom {
+    device: I2CRegisterDevice,
+}
+
+impl I2CEeprom {
+    /// Creates an EEPROM of `size` bytes (up to 64 KiB), erased to 0xff. `page_size` must divide
+    /// `size`.
+    pub fn new(address: u32, scl: Pin, sda: Pin, size: usize, page_size: usize) -> Self {
+        assert!(
+            (1..=0x10000).contains(&size),
+            "size must be between 1 and 65536 bytes"
+        );
+        assert!(
+            page_size > 0 && size.is_multiple_of(page_size),
+            "page_size must divide size"
+        );
+        let device = I2CRegisterDevice::with_layout(
+            address,
+            scl,
+            sda,
+            RegisterLayout {
+                size,
+                address_bytes: 2,
+                page_size,
+            },
+        );
+        device.load(0, &vec![0xff; size]);
+        Self { device }
+    }
+
+    /// Copies `bytes` to the start of the memory, e.g. to set up the contents before the
+    /// simulation starts. Like [`I2CRegisterDevice::set_register`], this only needs a shared
+    /// reference, so it also works on an EEPROM shared with callbacks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` is larger than the EEPROM.
+    pub fn load(&self, bytes: &[u8]) {
+        assert!(bytes.len() <= self.size(), "data is larger than the EEPROM");
+        self.device.load(0, bytes);
+    }
+
+    /// Returns a copy of the whole memory contents.
+    pub fn dump(&self) -> Vec<u8> {
+        self.device.registers().to_vec()
+    }
+
+    pub fn size(&self) -> usize {
+        self.device.registers().len()
+    }
+
+    pub fn device(&self) -> &I2CDevice {
+        self.device.device()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::VirtualI2cBus;
+    use crate::pin::PinMode;
+
+    // A 256-byte EEPROM with 16-byte pages.
+    fn eeprom() -> I2CEeprom {
+        let scl = Pin::new("SCL", PinMode::Input);
+        let sda = Pin::new("SDA", PinMode::Input);
+        I2CEeprom::new(0x50, scl, sda, 256, 16)
+    }
+
+    fn write(bus: &mut VirtualI2cBus, address: u16, data: &[u8]) {
+        assert!(bus.start(0x50, true));
+        for byte in address.to_be_bytes().iter().chain(data) {
+            assert!(bus.write(*byte));
+        }
+        bus.stop();
+    }
+
+    fn read(bus: &mut VirtualI2cBus, address: u16, len: usize) -> Vec<u8> {
+        assert!(bus.start(0x50, true));
+        for byte in address.to_be_bytes() {
+            assert!(bus.write(byte));
+        }
+        assert!(bus.start(0x50, false));
+        let data = (0..len).map(|_| bus.read()).collect();
+        bus.stop();
+        data
+    }
+
+    #[test]
+    fn loaded_contents_read_back_over_i2c() {
+        let eeprom = eeprom();
+        eeprom.load(b"Hello");
+        let mut bus = VirtualI2cBus::new();
+        assert_eq!(read(&mut bus, 0, 6), b"Hello\xff");
+        // Reads wrap around at the end of the memory.
+        assert_eq!(read(&mut bus, 0xff, 3), b"\xffHe");
+    }
+
+    #[test]
+    fn writes_wrap_within_the_page() {
+        let eeprom = eeprom();
+        let mut bus = VirtualI2cBus::new();
+        write(&mut bus, 0x1e, b"ABCD");
+        let memory = eeprom.dump();
+        assert_eq!(memory.len(), 256);
+        assert_eq!(&memory[0x1e..0x20], b"AB");
+        assert_eq!(&memory[0x10..0x12], b"CD");
+        assert_eq!(memory[0x20], 0xff);
+    }
+
+    #[test]
+    fn dump_is_a_copy() {
+        let eeprom = eeprom();
+        let before = eeprom.dump();
+        let mut bus = VirtualI2cBus::new();
+        write(&mut bus, 0, b"X");
+        assert_eq!(before[0], 0xff);
+        assert_eq!(eeprom.dump()[0], b'X');
+    }
+
+    #[test]
+    #[should_panic(expected = "data is larger than the EEPROM")]
+    fn loading_too_much_data_panics() {
+        eeprom().load(&[0; 257]);
+    }
+}
@@ -0,0 +1,261 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::{Cell, Ref, RefCell};
+use std::rc::Rc;
+
+use crate::i2c::{I2CAck, I2CDevice, I2CDeviceConfig};
+use crate::pin::Pin;
+
+type RegisterWriteHook = Box<dyn FnMut(usize, u8) + 'static>;
+
+/// How the registers of an [`I2CRegisterDevice`] are addressed.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RegisterLayout {
+    pub(crate) size: usize,
+    /// Number of bytes at the start of each write transaction that select the register (1 or 2,
+    /// most significant byte first).
+    pub(crate) address_bytes: usize,
+    /// Writes wrap around within a page of this size. Reads always advance through the whole
+    /// register space.
+    pub(crate) page_size: usize,
+}
+
+struct RegisterFile {
+    registers: RefCell<Vec<u8>>,
+    layout: RegisterLayout,
+    pointer: Cell<usize>,
+    // The first bytes of each write transaction set the register pointer.
+    address_bytes_pending: Cell<usize>,
+    write_hook: RefCell<Option<RegisterWriteHook>>,
+}
+
+impl RegisterFile {
+    fn advance_read(&self) {
+        self.pointer
+            .set((self.pointer.get() + 1) % self.layout.size);
+    }
+
+    fn advance_write(&self) {
+        let pointer = self.pointer.get();
+        let page_start = pointer - pointer % self.layout.page_size;
+        self.pointer
+            .set(page_start + (pointer + 1 - page_start) % self.layout.page_size);
+    }
+}
+
+/// An I2C device exposing a set of numbered 8-bit registers, as most I2C sensors do.
+///
+/// The first byte of each write transaction selects a register, and the following bytes are
+/// written to it. Reads return the value of the selected register. Each byte read or written
+/// advances to the next register, wrapping around after the last one.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::i2c_register::I2CRegisterDevice;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let scl = Pin::new("SCL", PinMode::Input);
+/// let sda = Pin::new("SDA", PinMode::Input);
+/// let sensor = I2CRegisterDevice::new(0x48, scl, sda, 4);
+/// sensor.set_register(0, 0x19); // Temperature: 25°C
+/// sensor.on_register_write(|register, value| {
+///     println!("Register {} set to 0x{:02x}", register, value);
+/// });
+/// ```
+///
+pub struct I2CRegisterDevice {
+    device: I2CDevice,
+    registers: Rc<RegisterFile>,
+}
+
+impl I2CRegisterDevice {
+    /// Creates a device with `register_count` registers (1 - 256), all initialized to zero.
+    pub fn new(address: u32, scl: Pin, sda: Pin, register_count: usize) -> Self {
+        assert!(
+            (1..=256).contains(&register_count),
+            "register_count must be between 1 and 256"
+        );
+        Self::with_layout(
+            address,
+            scl,
+            sda,
+            RegisterLayout {
+                size: register_count,
+                address_bytes: 1,
+                page_size: register_count,
+            },
+        )
+    }
+
+    pub(crate) fn with_layout(address: u32, scl: Pin, sda: Pin, layout: RegisterLayout) -> Self {
+        let registers = Rc::new(RegisterFile {
+            registers: RefCell::new(vec![0; layout.size]),
+            layout,
+            pointer: Cell::new(0),
+            address_bytes_pending: Cell::new(0),
+            write_hook: RefCell::new(None),
+        });
+
+        let connect_registers = registers.clone();
+        let read_registers = registers.clone();
+        let write_registers = registers.clone();
+        let config = I2CDeviceConfig::builder(address, scl, sda)
+            .on_connect(move |_, write| {
+                if write {
+                    let registers = &connect_registers;
+                    registers
+                        .address_bytes_pending
+                        .set(registers.layout.address_bytes);
+                    registers.pointer.set(0);
+                }
+                true
+            })
+            .on_read(move || {
+                let value = read_registers.registers.borrow()[read_registers.pointer.get()];
+                read_registers.advance_read();
+                value
+            })
+            .on_write(move |data| {
+                let registers = &write_registers;
+                let pending = registers.address_bytes_pending.get();
+                if pending > 0 {
+                    registers.address_bytes_pending.set(pending - 1);
+                    let pointer =
+                        (registers.pointer.get() << 8 | data as usize) % registers.layout.size;
+                    registers.pointer.set(pointer);
+                    return I2CAck::Ack;
+                }
+                let register = registers.pointer.get();
+                registers.registers.borrow_mut()[register] = data;
+                registers.advance_write();
+                // Take the hook out while it runs, so that it can access the device or replace
+                // itself.
+                let hook = registers.write_hook.borrow_mut().take();
+                if let Some(mut hook) = hook {
+                    hook(register, data);
+                    let mut slot = registers.write_hook.borrow_mut();
+                    if slot.is_none() {
+                        *slot = Some(hook);
+                    }
+                }
+                I2CAck::Ack
+            })
+            .build();
+
+        Self {
+            device: I2CDevice::new(config),
+            registers,
+        }
+    }
+
+    pub fn register(&self, register: usize) -> u8 {
+        self.registers.registers.borrow()[register]
+    }
+
+    pub(crate) fn registers(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.registers.registers.borrow(), Vec::as_slice)
+    }
+
+    pub(crate) fn load(&self, offset: usize, bytes: &[u8]) {
+        self.registers.registers.borrow_mut()[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Sets the value of a register, e.g. to update a sensor reading. This doesn't call the
+    /// register write hook.
+    pub fn set_register(&self, register: usize, value: u8) {
+        self.registers.registers.borrow_mut()[register] = value;
+    }
+
+    /// Calls `hook` with the register number and the new value whenever the I2C controller writes
+    /// to a register. Replaces any previous hook, including from within the hook itself.
+    pub fn on_register_write<F>(&self, hook: F)
+    where
+        F: FnMut(usize, u8) + 'static,
+    {
+        *self.registers.write_hook.borrow_mut() = Some(Box::new(hook));
+    }
+
+    pub fn device(&self) -> &I2CDevice {
+        &self.device
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::VirtualI2cBus;
+    use crate::pin::PinMode;
+
+    fn device() -> Rc<I2CRegisterDevice> {
+        let scl = Pin::new("SCL", PinMode::Input);
+        let sda = Pin::new("SDA", PinMode::Input);
+        Rc::new(I2CRegisterDevice::new(0x48, scl, sda, 4))
+    }
+
+    #[test]
+    fn registers_auto_increment_on_write_and_read() {
+        let device = device();
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x48, true));
+        for byte in [2, 0xaa, 0xbb, 0xcc] {
+            assert!(bus.write(byte));
+        }
+        bus.stop();
+        // The third byte wrapped around to register 0.
+        assert_eq!(device.register(0), 0xcc);
+        assert_eq!(device.register(2), 0xaa);
+        assert_eq!(device.register(3), 0xbb);
+
+        device.set_register(1, 0x11);
+        assert!(bus.start(0x48, true));
+        assert!(bus.write(0));
+        assert!(bus.start(0x48, false));
+        let values: Vec<u8> = (0..5).map(|_| bus.read()).collect();
+        bus.stop();
+        assert_eq!(values, [0xcc, 0x11, 0xaa, 0xbb, 0xcc]);
+    }
+
+    #[test]
+    fn write_hook_can_access_the_device() {
+        let device = device();
+        let hook_device = device.clone();
+        // Mirror each write into the next register.
+        device.on_register_write(move |register, value| {
+            hook_device.set_register(register + 1, hook_device.register(register));
+            assert_eq!(value, hook_device.register(register));
+        });
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x48, true));
+        assert!(bus.write(0));
+        assert!(bus.write(0x42));
+        bus.stop();
+        assert_eq!(device.register(1), 0x42);
+    }
+
+    #[test]
+    fn write_hook_can_replace_itself() {
+        let device = device();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let hook_device = device.clone();
+        let hook_calls = calls.clone();
+        device.on_register_write(move |register, _| {
+            hook_calls.borrow_mut().push(("first", register));
+            let next_calls = hook_calls.clone();
+            hook_device.on_register_write(move |register, _| {
+                next_calls.borrow_mut().push(("second", register));
+            });
+        });
+        let mut bus = VirtualI2cBus::new();
+        assert!(bus.start(0x48, true));
+        for byte in [0, 1, 2, 3] {
+            assert!(bus.write(byte));
+        }
+        bus.stop();
+        assert_eq!(
+            *calls.borrow(),
+            [("first", 0), ("second", 1), ("second", 2)]
+        );
+    }
+}
@@ -1,6 +1,29 @@
 // SPDX-License-Identifier: MIT
 // Author: Uri Shaked
 
+pub mod attr;
+pub mod blink;
+pub mod bus;
+pub mod chip;
+pub mod executor;
+pub mod framebuffer;
 pub mod i2c;
+pub mod i2c_async;
+pub mod i2c_eeprom;
+pub mod i2c_register;
+#[cfg(feature = "log")]
+pub mod logger;
+pub mod measure;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod pin;
 pub mod println;
+pub mod pwm;
+pub mod scheduler;
+pub mod shift;
+pub mod spi;
+#[cfg(feature = "stack-printer")]
+pub mod stack_printer;
+pub mod time;
+pub mod timer;
+pub mod uart;
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! A [`log`] backend printing to the Wokwi debug console. Requires the `log` feature.
+//!
+//! Example:
+//!
+//! ```rust,no_run
+//! use log::{info, LevelFilter};
+//!
+//! wokwi_chips_api::logger::init(LevelFilter::Info).unwrap();
+//! info!("chip started");
+//! // In a chip crate named my_chip, prints "[INFO] my_chip: chip started".
+//! ```
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+/// Prints each log record on a line of its own, as `[LEVEL] target: message`.
+pub struct WokwiLogger;
+
+static LOGGER: WokwiLogger = WokwiLogger;
+
+impl Log for WokwiLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            crate::println!(
+                "[{}] {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Registers [`WokwiLogger`] as the logger, and sets the maximum log level. Fails if a logger
+/// was already registered.
+pub fn init(max_level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use log::{debug, info, warn};
+
+    // The logger is registered for the whole process, so a single test registers it.
+    #[test]
+    fn log_macros_print_to_the_debug_console() {
+        init(LevelFilter::Info).unwrap();
+        assert!(init(LevelFilter::Trace).is_err());
+
+        info!("chip started");
+        debug!("not printed");
+        warn!(target: "i2c", "NACK from 0x{:02x}", 0x42);
+        assert_eq!(
+            mock::take_output(),
+            "[INFO] wokwi_chips_api::logger::tests: chip started\n[WARN] i2c: NACK from 0x42\n"
+        );
+    }
+}
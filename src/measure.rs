@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use crate::pin::{Pin, PinValue, WatchEdge};
+use crate::time::sim_nanos;
+use crate::timer::Timer;
+
+/// Counts the edges on a pin.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::measure::PulseCounter;
+/// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+///
+/// let encoder = Pin::new("ENC", PinMode::Input);
+/// let counter = PulseCounter::new(encoder, WatchEdge::Rising);
+/// // Later:
+/// let ticks = counter.count();
+/// counter.reset();
+/// ```
+///
+/// A windowed counter (see [`PulseCounter::windowed`]) also reports the number of pulses per
+/// window, e.g. for a tachometer:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::measure::PulseCounter;
+/// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+///
+/// let tach = Pin::new("TACH", PinMode::Input);
+/// // The fan sends 2 pulses per revolution.
+/// let counter = PulseCounter::windowed(tach, WatchEdge::Rising, Duration::from_secs(1));
+/// // Later:
+/// let rpm = counter.rate().unwrap_or(0) * 60 / 2;
+/// ```
+///
+pub struct PulseCounter {
+    pin: Pin,
+    count: Rc<AtomicU32>,
+    window: Option<Window>,
+}
+
+struct Window {
+    rate: Rc<Cell<Option<u32>>>,
+    _timer: Timer,
+}
+
+impl PulseCounter {
+    /// Starts counting the given edges on `pin`. The count wraps around on overflow.
+    pub fn new(pin: Pin, edge: WatchEdge) -> Self {
+        let count = Rc::new(AtomicU32::new(0));
+        let watch_count = count.clone();
+        pin.watch(edge, move |_, _| {
+            watch_count.fetch_add(1, Ordering::Relaxed);
+        });
+
+        Self {
+            pin,
+            count,
+            window: None,
+        }
+    }
+
+    /// Like [`PulseCounter::new`], but also counts the edges in consecutive windows of length
+    /// `window`, starting now, reported by [`PulseCounter::rate`]. A periodic timer takes the
+    /// count of each window when it ends, and resets it for the next one.
+    ///
+    /// The windowed count is kept apart from [`PulseCounter::count`], so resetting one doesn't
+    /// affect the other.
+    pub fn windowed(pin: Pin, edge: WatchEdge, window: Duration) -> Self {
+        let count = Rc::new(AtomicU32::new(0));
+        let window_count = Rc::new(AtomicU32::new(0));
+        let watch_count = count.clone();
+        let watch_window_count = window_count.clone();
+        pin.watch(edge, move |_, _| {
+            watch_count.fetch_add(1, Ordering::Relaxed);
+            watch_window_count.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let rate = Rc::new(Cell::new(None));
+        let timer_rate = rate.clone();
+        let timer = Timer::periodic(window, move || {
+            timer_rate.set(Some(window_count.swap(0, Ordering::Relaxed)));
+        });
+
+        Self {
+            pin,
+            count,
+            window: Some(Window {
+                rate,
+                _timer: timer,
+            }),
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of edges in the most recent complete window of a windowed counter.
+    ///
+    /// Returns `None` until the first window is complete, rather than a partial count that would
+    /// read as a low rate, and always for a counter created with [`PulseCounter::new`].
+    pub fn rate(&self) -> Option<u32> {
+        self.window.as_ref()?.rate.get()
+    }
+
+    /// Resets the count to zero and returns the count before the reset.
+    pub fn reset(&self) -> u32 {
+        self.count.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn pin(&self) -> &Pin {
+        &self.pin
+    }
+}
+
+/// Measures the frequency of a square wave on a pin, from the timestamps of its most recent
+/// rising edges.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::measure::FrequencyCounter;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let tach = Pin::new("TACH", PinMode::Input);
+/// let counter = FrequencyCounter::new(tach, 8);
+/// // Later:
+/// let rpm = counter.frequency_hz() * 60.0;
+/// ```
+///
+pub struct FrequencyCounter {
+    pin: Pin,
+    edges: Rc<RefCell<VecDeque<u64>>>,
+}
+
+impl FrequencyCounter {
+    /// Starts measuring the frequency on `pin`, averaging over the last `window` periods.
+    pub fn new(pin: Pin, window: usize) -> Self {
+        let capacity = window.max(1) + 1;
+        let edges = Rc::new(RefCell::new(VecDeque::with_capacity(capacity)));
+        let watch_edges = edges.clone();
+        pin.watch(WatchEdge::Rising, move |_, _| {
+            let mut edges = watch_edges.borrow_mut();
+            if edges.len() == capacity {
+                edges.pop_front();
+            }
+            edges.push_back(sim_nanos());
+        });
+
+        Self { pin, edges }
+    }
+
+    /// Returns the frequency in Hz, or 0 until at least two rising edges have been seen.
+    ///
+    /// The value reflects the most recent edges, so it holds its last value if the signal stops.
+    pub fn frequency_hz(&self) -> f64 {
+        let edges = self.edges.borrow();
+        match (edges.front(), edges.back()) {
+            (Some(first), Some(last)) if last > first => {
+                (edges.len() - 1) as f64 * 1e9 / (last - first) as f64
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Forgets all the edges seen so far.
+    pub fn reset(&self) {
+        self.edges.borrow_mut().clear();
+    }
+
+    pub fn pin(&self) -> &Pin {
+        &self.pin
+    }
+}
+
+#[derive(Default)]
+struct PulseWidths {
+    last_edge: Cell<Option<u64>>,
+    high: Cell<Option<Duration>>,
+    low: Cell<Option<Duration>>,
+}
+
+/// Measures the width of the high and low pulses on a pin.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::measure::PulseWidthMonitor;
+/// use wokwi_chips_api::pin::{Pin, PinMode, PinValue};
+/// use wokwi_chips_api::println;
+///
+/// let input = Pin::new("PWM", PinMode::Input);
+/// let monitor = PulseWidthMonitor::new(input, |width, level| {
+///     if level == PinValue::High {
+///         println!("High pulse: {:?}", width);
+///     }
+/// });
+/// ```
+///
+pub struct PulseWidthMonitor {
+    pin: Pin,
+    widths: Rc<PulseWidths>,
+}
+
+impl PulseWidthMonitor {
+    /// Starts measuring pulses on `pin`. `callback` is called at the end of each pulse with its
+    /// width and level, i.e. [`PinValue::High`] for a pulse that ends with a falling edge.
+    ///
+    /// The first edge only marks the start of the first pulse, so it doesn't call `callback`.
+    pub fn new<F>(pin: Pin, mut callback: F) -> Self
+    where
+        F: FnMut(Duration, PinValue) + 'static,
+    {
+        let widths = Rc::new(PulseWidths::default());
+        let watch_widths = widths.clone();
+        pin.watch(WatchEdge::Both, move |_, value| {
+            let now = sim_nanos();
+            let Some(last_edge) = watch_widths.last_edge.replace(Some(now)) else {
+                return;
+            };
+            let width = Duration::from_nanos(now.saturating_sub(last_edge));
+            let level = !value;
+            match level {
+                PinValue::High => watch_widths.high.set(Some(width)),
+                PinValue::Low => watch_widths.low.set(Some(width)),
+            }
+            callback(width, level);
+        });
+
+        Self { pin, widths }
+    }
+
+    /// Returns the width of the last complete high pulse.
+    pub fn last_high_width(&self) -> Option<Duration> {
+        self.widths.high.get()
+    }
+
+    /// Returns the width of the last complete low pulse.
+    pub fn last_low_width(&self) -> Option<Duration> {
+        self.widths.low.get()
+    }
+
+    pub fn pin(&self) -> &Pin {
+        &self.pin
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use crate::pin::PinMode;
+
+    fn pulse(name: &str, count: usize) {
+        for _ in 0..count {
+            mock::set_input(name, PinValue::High);
+            mock::advance(Duration::from_micros(250));
+            mock::set_input(name, PinValue::Low);
+            mock::advance(Duration::from_micros(250));
+        }
+    }
+
+    #[test]
+    fn pulse_counter_counts_each_edge() {
+        let counter = PulseCounter::new(Pin::new("ENC", PinMode::Input), WatchEdge::Rising);
+        pulse("ENC", 25);
+        assert_eq!(counter.count(), 25);
+        assert_eq!(counter.reset(), 25);
+        pulse("ENC", 3);
+        assert_eq!(counter.count(), 3);
+    }
+
+    #[test]
+    fn windowed_rate_is_none_until_the_first_window_closes() {
+        let tach = Pin::new("TACH", PinMode::Input);
+        let counter = PulseCounter::windowed(tach, WatchEdge::Rising, Duration::from_millis(10));
+        assert_eq!(counter.rate(), None);
+        pulse("TACH", 19);
+        // 9.5 ms in, the first window is still open.
+        assert_eq!(counter.rate(), None);
+        mock::advance(Duration::from_micros(500));
+        assert_eq!(counter.rate(), Some(19));
+    }
+
+    #[test]
+    fn windowed_rate_reports_the_last_complete_window() {
+        let tach = Pin::new("TACH", PinMode::Input);
+        let counter = PulseCounter::windowed(tach, WatchEdge::Rising, Duration::from_millis(10));
+        pulse("TACH", 10);
+        assert_eq!(counter.rate(), None);
+        pulse("TACH", 20);
+        // 15 ms in, the second window isn't complete yet.
+        assert_eq!(counter.rate(), Some(20));
+        pulse("TACH", 10);
+        assert_eq!(counter.rate(), Some(20));
+        // A window without pulses reports zero.
+        mock::advance(Duration::from_millis(10));
+        assert_eq!(counter.rate(), Some(0));
+        assert_eq!(counter.count(), 40);
+    }
+
+    #[test]
+    fn resetting_the_count_keeps_the_window_count() {
+        let tach = Pin::new("TACH", PinMode::Input);
+        let counter = PulseCounter::windowed(tach, WatchEdge::Rising, Duration::from_millis(10));
+        pulse("TACH", 5);
+        assert_eq!(counter.reset(), 5);
+        pulse("TACH", 15);
+        assert_eq!(counter.rate(), Some(20));
+        assert_eq!(counter.count(), 15);
+    }
+
+    #[test]
+    fn rate_is_none_without_a_window() {
+        let counter = PulseCounter::new(Pin::new("ENC", PinMode::Input), WatchEdge::Rising);
+        pulse("ENC", 50);
+        assert_eq!(counter.rate(), None);
+    }
+}
@@ -0,0 +1,1382 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! An in-process stand-in for the simulator, for testing chip code on the host. Requires the
+//! `mock` feature.
+//!
+//! With the feature enabled, the crate defines the `wokwi_chip_ll` functions itself, backed by
+//! the in-memory state of this module, so chip code runs as is in `cargo test`. Tests then play
+//! the part of the rest of the circuit: they drive the chip's inputs with [`set_input`], move the
+//! simulation clock with [`advance`], and check the chip's outputs with [`pin_value`] and
+//! [`take_output`].
+//!
+//! The state is thread-local, and each test runs on a thread of its own, so tests don't see each
+//! other's pins. [`reset`] clears the state within a test.
+//!
+//! Don't enable the feature when building the chip for the simulator: the simulator's functions
+//! would clash with the mock ones.
+//!
+//! Example, a test driving a watch callback:
+//!
+//! ```rust
+//! use std::cell::Cell;
+//! use std::rc::Rc;
+//! use wokwi_chips_api::mock;
+//! use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+//!
+//! let input = Pin::new("IN", PinMode::Input);
+//! let output = Pin::new("OUT", PinMode::OutputLow);
+//! let edges = Rc::new(Cell::new(0));
+//! let watch_edges = edges.clone();
+//! input.watch(WatchEdge::Rising, move |_, _| {
+//!     watch_edges.set(watch_edges.get() + 1);
+//!     output.toggle();
+//! });
+//!
+//! mock::set_input("IN", PinValue::High);
+//! mock::set_input("IN", PinValue::Low);
+//! mock::set_input("IN", PinValue::High);
+//! assert_eq!(edges.get(), 2);
+//! assert_eq!(mock::pin_value("OUT"), PinValue::Low);
+//! ```
+//!
+//! Work that the chip defers to a timer, even a zero-length one, only runs once the test moves
+//! the clock with [`advance`], e.g. a [`PinBus::watch`](crate::bus::PinBus::watch) callback.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use wokwi_chip_ll::{
+    AttrId, BufferId, I2CConfig, I2CDevId, PinId, SPIConfig, SPIDevId, TimerConfig, TimerId,
+    UARTConfig, UARTDevId, WatchConfig, BOTH, FALLING, RISING,
+};
+
+use crate::pin::{PinMode, PinValue};
+
+struct MockPin {
+    name: String,
+    mode: u32,
+    value: u32,
+    analog_input: f32,
+    analog_output: f32,
+    watch: Option<MockWatch>,
+    // Set by tests to make the chip's watches fail.
+    refuse_watch: bool,
+}
+
+#[derive(Clone, Copy)]
+struct MockWatch {
+    user_data: *const c_void,
+    edge: u32,
+    pin_change: *const c_void,
+}
+
+struct MockTimer {
+    user_data: *const c_void,
+    callback: *const c_void,
+    deadline: Option<u64>,
+    period: u64,
+    repeat: bool,
+}
+
+struct MockUart {
+    user_data: *const c_void,
+    rx_data: *const c_void,
+    write_done: *const c_void,
+    written: Vec<u8>,
+    writing: bool,
+    // Set by tests to make writes fail.
+    busy: bool,
+}
+
+#[derive(Clone, Copy)]
+struct MockI2C {
+    user_data: *const c_void,
+    address: u32,
+    connect: *const c_void,
+    read: *const c_void,
+    write: *const c_void,
+    disconnect: *const c_void,
+}
+
+struct MockSpi {
+    user_data: *const c_void,
+    done: *const c_void,
+    // The buffer of the transfer in progress, its length, and the number of bytes transferred.
+    transfer: Option<(*mut u8, usize, usize)>,
+    // The number of spiStop calls.
+    stops: usize,
+}
+
+#[derive(Default)]
+struct MockState {
+    nanos: u64,
+    pins: Vec<MockPin>,
+    timers: Vec<MockTimer>,
+    uarts: Vec<MockUart>,
+    i2c_devices: Vec<MockI2C>,
+    spi_devices: Vec<MockSpi>,
+    // Attribute values set by tests, and the values of the initialized attributes.
+    attribute_values: Vec<(String, f64)>,
+    attributes: Vec<f64>,
+    framebuffer_size: (u32, u32),
+    framebuffer: Vec<u8>,
+    output: String,
+    // The events recorded by a `Recorder`, while one is running.
+    recording: Option<Vec<Event>>,
+}
+
+thread_local! {
+    static STATE: RefCell<MockState> = RefCell::new(MockState::default());
+}
+
+/// Clears all the mock state: pins, timers, devices, attributes, the clock and the output.
+///
+/// The pins, timers and devices of the chip still refer to the old state, so drop them before
+/// resetting, and create new ones afterwards.
+pub fn reset() {
+    STATE.set(MockState::default());
+}
+
+fn with_state<R>(f: impl FnOnce(&mut MockState) -> R) -> R {
+    STATE.with_borrow_mut(f)
+}
+
+fn record(state: &mut MockState, kind: PinEventKind, index: usize, value: u32) {
+    if let Some(events) = state.recording.as_mut() {
+        events.push(Event::Pin(PinEvent {
+            nanos: state.nanos,
+            kind,
+            pin: state.pins[index].name.clone(),
+            value: PinValue::from(value != 0),
+        }));
+    }
+}
+
+fn record_i2c(transfer: I2cTransfer) {
+    with_state(|state| {
+        if let Some(events) = state.recording.as_mut() {
+            events.push(Event::I2c(I2cEvent {
+                nanos: state.nanos,
+                transfer,
+            }));
+        }
+    });
+}
+
+fn pin_index(state: &MockState, name: &str) -> usize {
+    state
+        .pins
+        .iter()
+        .position(|pin| pin.name == name)
+        .unwrap_or_else(|| panic!("no pin named {:?}", name))
+}
+
+/// Drives an input pin, the way the circuit would. If the value changes, the pin's watch is
+/// called.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn set_input(name: &str, value: PinValue) {
+    let change = with_state(|state| {
+        let index = pin_index(state, name);
+        let value = value as u32;
+        record(state, PinEventKind::Input, index, value);
+        let pin = &mut state.pins[index];
+        if pin.value == value {
+            return None;
+        }
+        pin.value = value;
+        let watch = pin.watch?;
+        let edge = if value != 0 { RISING } else { FALLING };
+        (watch.edge == BOTH || watch.edge == edge).then_some((watch, index as PinId, value))
+    });
+    // The state isn't borrowed while the callback runs, since it calls back into the mock.
+    if let Some((watch, pin, value)) = change {
+        let pin_change: extern "C" fn(*mut c_void, PinId, u32) =
+            unsafe { mem::transmute(watch.pin_change) };
+        pin_change(watch.user_data as *mut c_void, pin, value);
+    }
+}
+
+/// Returns the value of a pin, as last written by the chip or set by [`set_input`].
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn pin_value(name: &str) -> PinValue {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        PinValue::from(state.pins[index].value != 0)
+    })
+}
+
+/// Returns the mode of a pin.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn pin_mode(name: &str) -> PinMode {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        PinMode::try_from(state.pins[index].mode).expect("the pin mode is valid")
+    })
+}
+
+/// Returns true if the chip watches the pin.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn is_watched(name: &str) -> bool {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        state.pins[index].watch.is_some()
+    })
+}
+
+/// Sets the voltage the chip reads from an analog pin.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn set_analog_input(name: &str, voltage: f32) {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        state.pins[index].analog_input = voltage;
+    });
+}
+
+/// Returns the voltage last written by the chip to an analog pin.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn analog_output(name: &str) -> f32 {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        state.pins[index].analog_output
+    })
+}
+
+/// Sets the value of an attribute, as in `diagram.json`. Only attributes initialized after this
+/// call see the value.
+pub fn set_attribute(name: &str, value: f64) {
+    with_state(|state| {
+        state
+            .attribute_values
+            .retain(|(attr_name, _)| attr_name != name);
+        state.attribute_values.push((name.to_string(), value));
+    });
+}
+
+/// Changes the value of an initialized attribute, as a slider control does while the simulation
+/// runs.
+pub fn change_attribute(attr: AttrId, value: f64) {
+    with_state(|state| state.attributes[attr as usize] = value);
+}
+
+/// Sets the size of the display returned to the chip when it initializes its framebuffer.
+pub fn set_framebuffer_size(width: u32, height: u32) {
+    with_state(|state| state.framebuffer_size = (width, height));
+}
+
+/// Returns the framebuffer contents, 4 bytes per pixel.
+pub fn framebuffer() -> Vec<u8> {
+    with_state(|state| state.framebuffer.clone())
+}
+
+/// Returns the text printed to the debug console so far, and clears it.
+pub fn take_output() -> String {
+    with_state(|state| mem::take(&mut state.output))
+}
+
+/// Advances the simulation clock by `duration`, firing the timers that are due on the way, in
+/// order. Pending UART writes complete first.
+pub fn advance(duration: Duration) {
+    complete_uart_writes();
+    let end = with_state(|state| state.nanos) + duration.as_nanos() as u64;
+    loop {
+        let due = with_state(|state| {
+            let (index, deadline) = state
+                .timers
+                .iter()
+                .enumerate()
+                .filter_map(|(index, timer)| Some((index, timer.deadline?)))
+                .filter(|&(_, deadline)| deadline <= end)
+                .min_by_key(|&(_, deadline)| deadline)?;
+            state.nanos = deadline;
+            let timer = &mut state.timers[index];
+            timer.deadline = (timer.repeat && timer.period > 0).then(|| deadline + timer.period);
+            Some((timer.user_data, timer.callback))
+        });
+        let Some((user_data, callback)) = due else {
+            break;
+        };
+        let callback: extern "C" fn(*mut c_void) = unsafe { mem::transmute(callback) };
+        callback(user_data as *mut c_void);
+    }
+    with_state(|state| state.nanos = end);
+}
+
+/// Sends bytes to the chip's UART, as if they came in on its RX pin.
+pub fn uart_receive(uart: UARTDevId, bytes: &[u8]) {
+    let (user_data, rx_data) = with_state(|state| {
+        let uart = &state.uarts[uart as usize];
+        (uart.user_data, uart.rx_data)
+    });
+    if rx_data.is_null() {
+        return;
+    }
+    let rx_data: extern "C" fn(*mut c_void, u8) = unsafe { mem::transmute(rx_data) };
+    for &byte in bytes {
+        rx_data(user_data as *mut c_void, byte);
+    }
+}
+
+/// Returns the bytes the chip wrote to a UART so far, and clears them.
+pub fn take_uart_output(uart: UARTDevId) -> Vec<u8> {
+    with_state(|state| mem::take(&mut state.uarts[uart as usize].written))
+}
+
+/// Completes the UART writes in progress, calling their `write_done` callbacks. The mock
+/// transmits instantly, but completes writes only here (or in [`advance`]), as the simulator
+/// completes them later.
+pub fn complete_uart_writes() {
+    let done: Vec<_> = with_state(|state| {
+        state
+            .uarts
+            .iter_mut()
+            .filter_map(|uart| {
+                let writing = mem::take(&mut uart.writing);
+                (writing && !uart.write_done.is_null()).then_some((uart.user_data, uart.write_done))
+            })
+            .collect()
+    });
+    for (user_data, write_done) in done {
+        let write_done: extern "C" fn(*mut c_void) = unsafe { mem::transmute(write_done) };
+        write_done(user_data as *mut c_void);
+    }
+}
+
+/// Makes the simulator refuse to watch the pin `name` while `refuse` is true, so that the chip's
+/// watches on it fail.
+///
+/// # Panics
+///
+/// Panics if the chip has no pin named `name`.
+pub fn set_watch_refused(name: &str, refuse: bool) {
+    with_state(|state| {
+        let index = pin_index(state, name);
+        state.pins[index].refuse_watch = refuse;
+    });
+}
+
+/// Makes the chip's writes to a UART fail while `busy` is true, as they do when the simulator is
+/// busy. Unlike for a write in progress, the chip's `write_done` callback isn't called when the
+/// UART stops being busy.
+pub fn set_uart_busy(uart: UARTDevId, busy: bool) {
+    with_state(|state| state.uarts[uart as usize].busy = busy);
+}
+
+/// Clocks bytes through the SPI transfer in progress, as a controller would: each byte of `mosi`
+/// goes to the chip, and the byte the chip sends back is returned. The chip's done callback is
+/// called once the transfer is complete. Bytes sent while no transfer is in progress are ignored.
+pub fn spi_transfer(spi: SPIDevId, mosi: &[u8]) -> Vec<u8> {
+    let mut miso = Vec::new();
+    for &byte in mosi {
+        let complete = with_state(|state| {
+            let device = &mut state.spi_devices[spi as usize];
+            let (buffer, len, position) = device.transfer.as_mut()?;
+            // The chip owns the buffer until the transfer is done.
+            let slot = unsafe { &mut *buffer.add(*position) };
+            miso.push(*slot);
+            *slot = byte;
+            *position += 1;
+            (*position == *len).then(|| spi_done(device))
+        });
+        if let Some(Some(done)) = complete {
+            done();
+        }
+    }
+    miso
+}
+
+/// Ends the transfer in progress of an SPI device, returning a closure that calls its done
+/// callback, to call once the state isn't borrowed.
+fn spi_done(device: &mut MockSpi) -> Option<impl FnOnce()> {
+    let (buffer, _, position) = device.transfer.take()?;
+    let user_data = device.user_data;
+    let done: extern "C" fn(*mut c_void, *mut u8, u32) = unsafe { mem::transmute(device.done) };
+    Some(move || done(user_data as *mut c_void, buffer, position as u32))
+}
+
+/// Returns the number of times the chip stopped a transfer of an SPI device.
+pub fn spi_stop_count(spi: SPIDevId) -> usize {
+    with_state(|state| state.spi_devices[spi as usize].stops)
+}
+
+/// Returns the number of timers the chip created. The simulator never frees a timer, so this
+/// only grows.
+pub fn timer_count() -> usize {
+    with_state(|state| state.timers.len())
+}
+
+/// Returns the addresses of the chip's I2C devices, in the order they were created.
+pub fn i2c_addresses() -> Vec<u32> {
+    with_state(|state| {
+        state
+            .i2c_devices
+            .iter()
+            .map(|device| device.address)
+            .collect()
+    })
+}
+
+/// An I2C bus where the test is the controller, talking to the chip's I2C devices through
+/// their callbacks. While a [`Recorder`] runs, every call is recorded, with the chip's
+/// response.
+///
+/// Example, reading a register from a device:
+///
+/// ```rust
+/// use wokwi_chips_api::i2c_register::I2CRegisterDevice;
+/// use wokwi_chips_api::mock::VirtualI2cBus;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+///
+/// let scl = Pin::new("SCL", PinMode::Input);
+/// let sda = Pin::new("SDA", PinMode::Input);
+/// let device = I2CRegisterDevice::new(0x48, scl, sda, 16);
+/// device.set_register(0x03, 0x5a);
+///
+/// let mut bus = VirtualI2cBus::new();
+/// assert!(bus.start(0x48, true));
+/// assert!(bus.write(0x03));
+/// assert!(bus.start(0x48, false));
+/// assert_eq!(bus.read(), 0x5a);
+/// bus.stop();
+/// assert!(!bus.start(0x49, true));
+/// ```
+#[derive(Default)]
+pub struct VirtualI2cBus {
+    // The device addressed by the current transaction.
+    device: Option<MockI2C>,
+}
+
+impl VirtualI2cBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a transaction (or restarts the current one) with the device at `address`, writing
+    /// to it if `write` is true, or reading from it. Returns true if a device ACKed the address.
+    pub fn start(&mut self, address: u32, write: bool) -> bool {
+        let ack = self.connect(address, write);
+        record_i2c(I2cTransfer::Connect {
+            address,
+            write,
+            ack,
+        });
+        ack
+    }
+
+    fn connect(&mut self, address: u32, write: bool) -> bool {
+        let device = with_state(|state| {
+            state
+                .i2c_devices
+                .iter()
+                .find(|device| device.address == address)
+                .copied()
+        });
+        if let Some(current) = self.device.take() {
+            // A repeated start to another device ends the transaction with the current one.
+            if device.is_none_or(|device| device.user_data != current.user_data) {
+                Self::disconnect(current);
+            }
+        }
+        let Some(device) = device else {
+            return false;
+        };
+        let connect: extern "C" fn(*mut c_void, u32, bool) -> bool =
+            unsafe { mem::transmute(device.connect) };
+        let ack = connect(device.user_data as *mut c_void, address, write);
+        if ack {
+            self.device = Some(device);
+        }
+        ack
+    }
+
+    /// Writes a byte to the addressed device. Returns true if the device ACKed it, false if it
+    /// NACKed it or no device is addressed.
+    pub fn write(&mut self, data: u8) -> bool {
+        let ack = self.device.is_some_and(|device| {
+            let write: extern "C" fn(*mut c_void, u8) -> bool =
+                unsafe { mem::transmute(device.write) };
+            write(device.user_data as *mut c_void, data)
+        });
+        record_i2c(I2cTransfer::Write { data, ack });
+        ack
+    }
+
+    /// Reads a byte from the addressed device, or `0xff` if no device is addressed.
+    pub fn read(&mut self) -> u8 {
+        let data = self.device.map_or(0xff, |device| {
+            let read: extern "C" fn(*mut c_void) -> u8 = unsafe { mem::transmute(device.read) };
+            read(device.user_data as *mut c_void)
+        });
+        record_i2c(I2cTransfer::Read { data });
+        data
+    }
+
+    /// Ends the transaction.
+    pub fn stop(&mut self) {
+        if let Some(device) = self.device.take() {
+            Self::disconnect(device);
+        }
+        record_i2c(I2cTransfer::Disconnect);
+    }
+
+    fn disconnect(device: MockI2C) {
+        let disconnect: extern "C" fn(*mut c_void) = unsafe { mem::transmute(device.disconnect) };
+        disconnect(device.user_data as *mut c_void);
+    }
+}
+
+/// What a [`PinEvent`] records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PinEventKind {
+    /// The test drove the pin, with [`set_input`].
+    Input,
+    /// The chip read the pin.
+    Read,
+    /// The chip wrote the pin.
+    Write,
+}
+
+/// A timestamped pin access, recorded by a [`Recorder`].
+///
+/// Events are saved one per line, as the time in nanoseconds, the kind, the pin name and the
+/// value, e.g. `1500 write LED 1`:
+///
+/// ```rust
+/// use wokwi_chips_api::mock::{PinEvent, PinEventKind};
+/// use wokwi_chips_api::pin::PinValue;
+///
+/// let event: PinEvent = "1500 write LED 1".parse().unwrap();
+/// assert_eq!(event.nanos, 1500);
+/// assert_eq!(event.kind, PinEventKind::Write);
+/// assert_eq!(event.pin, "LED");
+/// assert_eq!(event.value, PinValue::High);
+/// assert_eq!(event.to_string(), "1500 write LED 1");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinEvent {
+    /// The simulation time of the event, in nanoseconds.
+    pub nanos: u64,
+    pub kind: PinEventKind,
+    pub pin: String,
+    pub value: PinValue,
+}
+
+impl fmt::Display for PinEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            PinEventKind::Input => "input",
+            PinEventKind::Read => "read",
+            PinEventKind::Write => "write",
+        };
+        write!(
+            f,
+            "{} {} {} {}",
+            self.nanos, kind, self.pin, self.value as u32
+        )
+    }
+}
+
+/// Error returned when parsing an event from a line that isn't in the recording format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEventError {
+    line: String,
+}
+
+impl fmt::Display for ParseEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid recorded event: {:?}", self.line)
+    }
+}
+
+impl std::error::Error for ParseEventError {}
+
+impl ParseEventError {
+    fn new(line: &str) -> Self {
+        ParseEventError {
+            line: line.to_string(),
+        }
+    }
+}
+
+impl FromStr for PinEvent {
+    type Err = ParseEventError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        let nanos = fields.next().and_then(|nanos| nanos.parse().ok());
+        let kind = match fields.next() {
+            Some("input") => Some(PinEventKind::Input),
+            Some("read") => Some(PinEventKind::Read),
+            Some("write") => Some(PinEventKind::Write),
+            _ => None,
+        };
+        let pin = fields.next();
+        let value = match fields.next() {
+            Some("0") => Some(PinValue::Low),
+            Some("1") => Some(PinValue::High),
+            _ => None,
+        };
+        match (nanos, kind, pin, value, fields.next()) {
+            (Some(nanos), Some(kind), Some(pin), Some(value), None) => Ok(PinEvent {
+                nanos,
+                kind,
+                pin: pin.to_string(),
+                value,
+            }),
+            _ => Err(ParseEventError::new(line)),
+        }
+    }
+}
+
+/// A [`VirtualI2cBus`] call, with the chip's response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum I2cTransfer {
+    /// [`VirtualI2cBus::start`]: the test addressed a device, and `ack` is true if the chip
+    /// ACKed the address.
+    Connect {
+        address: u32,
+        write: bool,
+        ack: bool,
+    },
+    /// [`VirtualI2cBus::write`]: the test wrote `data`, and `ack` is true if the chip ACKed it.
+    Write { data: u8, ack: bool },
+    /// [`VirtualI2cBus::read`]: the test read `data` from the chip.
+    Read { data: u8 },
+    /// [`VirtualI2cBus::stop`]: the test ended the transaction.
+    Disconnect,
+}
+
+/// A timestamped I2C transfer, recorded by a [`Recorder`].
+///
+/// Events are saved one per line, as the time in nanoseconds, `i2c`, and the transfer:
+///
+/// ```rust
+/// use wokwi_chips_api::mock::{I2cEvent, I2cTransfer};
+///
+/// let lines = [
+///     "1500 i2c connect 0x50 write ack",
+///     "1600 i2c write 0x12 ack",
+///     "1700 i2c connect 0x50 read ack",
+///     "1800 i2c read 0x5a",
+///     "1900 i2c disconnect",
+/// ];
+/// let events: Vec<I2cEvent> = lines.iter().map(|line| line.parse().unwrap()).collect();
+/// assert_eq!(events[1].nanos, 1600);
+/// assert_eq!(events[1].transfer, I2cTransfer::Write { data: 0x12, ack: true });
+/// assert_eq!(events[3].to_string(), "1800 i2c read 0x5a");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct I2cEvent {
+    /// The simulation time of the event, in nanoseconds.
+    pub nanos: u64,
+    pub transfer: I2cTransfer,
+}
+
+impl fmt::Display for I2cEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ack = |ack| if ack { "ack" } else { "nack" };
+        write!(f, "{} i2c ", self.nanos)?;
+        match self.transfer {
+            I2cTransfer::Connect {
+                address,
+                write,
+                ack: acked,
+            } => {
+                let direction = if write { "write" } else { "read" };
+                write!(f, "connect {:#04x} {} {}", address, direction, ack(acked))
+            }
+            I2cTransfer::Write { data, ack: acked } => {
+                write!(f, "write {:#04x} {}", data, ack(acked))
+            }
+            I2cTransfer::Read { data } => write!(f, "read {:#04x}", data),
+            I2cTransfer::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+impl FromStr for I2cEvent {
+    type Err = ParseEventError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let hex = |field: &str| u32::from_str_radix(field.strip_prefix("0x")?, 16).ok();
+        let byte = |field: &str| hex(field).and_then(|value| u8::try_from(value).ok());
+        let ack = |field: &str| match field {
+            "ack" => Some(true),
+            "nack" => Some(false),
+            _ => None,
+        };
+        let transfer = match fields[..] {
+            [_, "i2c", "connect", address, direction, acked] => {
+                let write = match direction {
+                    "write" => Some(true),
+                    "read" => Some(false),
+                    _ => None,
+                };
+                (|| {
+                    Some(I2cTransfer::Connect {
+                        address: hex(address)?,
+                        write: write?,
+                        ack: ack(acked)?,
+                    })
+                })()
+            }
+            [_, "i2c", "write", data, acked] => (|| {
+                Some(I2cTransfer::Write {
+                    data: byte(data)?,
+                    ack: ack(acked)?,
+                })
+            })(),
+            [_, "i2c", "read", data] => byte(data).map(|data| I2cTransfer::Read { data }),
+            [_, "i2c", "disconnect"] => Some(I2cTransfer::Disconnect),
+            _ => None,
+        };
+        let nanos = fields.first().and_then(|nanos| nanos.parse().ok());
+        match (nanos, transfer) {
+            (Some(nanos), Some(transfer)) => Ok(I2cEvent { nanos, transfer }),
+            _ => Err(ParseEventError::new(line)),
+        }
+    }
+}
+
+/// An event recorded by a [`Recorder`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Pin(PinEvent),
+    I2c(I2cEvent),
+}
+
+impl Event {
+    /// The simulation time of the event, in nanoseconds.
+    pub fn nanos(&self) -> u64 {
+        match self {
+            Event::Pin(event) => event.nanos,
+            Event::I2c(event) => event.nanos,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Pin(event) => event.fmt(f),
+            Event::I2c(event) => event.fmt(f),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = ParseEventError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        if line.split_whitespace().nth(1) == Some("i2c") {
+            line.parse().map(Event::I2c)
+        } else {
+            line.parse().map(Event::Pin)
+        }
+    }
+}
+
+/// Records the test run: the pin inputs driven by the test, the chip's pin reads and writes,
+/// and the I2C transfers of [`VirtualI2cBus`]es, so that a failing scenario can be saved and
+/// replayed (see [`replay`]).
+///
+/// Example, replaying a recorded input sequence and checking the chip's output is the same:
+///
+/// ```rust
+/// use wokwi_chips_api::mock::{self, PinEventKind, Recorder, Recording};
+/// use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+/// use std::time::Duration;
+///
+/// fn chip() -> Pin {
+///     let output = Pin::new("OUT", PinMode::OutputLow);
+///     let input = Pin::new("IN", PinMode::Input);
+///     input.watch(WatchEdge::Both, move |_, value| output.write(!value));
+///     input
+/// }
+///
+/// let input = chip();
+/// let recorder = Recorder::start();
+/// mock::set_input("IN", PinValue::High);
+/// mock::advance(Duration::from_micros(10));
+/// mock::set_input("IN", PinValue::Low);
+/// let recording = recorder.stop();
+/// let path = std::env::temp_dir().join("wokwi_chips_api_recording.txt");
+/// recording.save(&path).unwrap();
+///
+/// drop(input);
+/// mock::reset();
+/// let _input = chip();
+/// let recorder = Recorder::start();
+/// mock::replay(&path).unwrap();
+/// assert_eq!(recorder.stop().writes(), recording.writes());
+/// # std::fs::remove_file(&path).ok();
+/// ```
+pub struct Recorder {
+    _private: (),
+}
+
+impl Recorder {
+    /// Starts recording, discarding the events of a recording in progress.
+    pub fn start() -> Self {
+        with_state(|state| state.recording = Some(Vec::new()));
+        Recorder { _private: () }
+    }
+
+    /// Stops recording, and returns the recorded events.
+    pub fn stop(self) -> Recording {
+        let events = with_state(|state| state.recording.take()).unwrap_or_default();
+        Recording { events }
+    }
+}
+
+/// The events recorded by a [`Recorder`], in the order they happened.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    /// Returns the pin writes of the chip, which is what a replay is usually checked against.
+    pub fn writes(&self) -> Vec<&PinEvent> {
+        self.events_of(PinEventKind::Write)
+    }
+
+    /// Returns the pin events of the given kind.
+    pub fn events_of(&self, kind: PinEventKind) -> Vec<&PinEvent> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Pin(event) if event.kind == kind => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the I2C transfers, with the chip's responses.
+    pub fn i2c_events(&self) -> Vec<&I2cEvent> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                Event::I2c(event) => Some(event),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Saves the recording to a file, one event per line.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Loads a recording saved by [`Recording::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    /// Feeds the recorded inputs back to the chip, advancing the clock to the time of each one:
+    /// the pin inputs, and the I2C transfers, on a single [`VirtualI2cBus`]. The recorded pin
+    /// reads and writes, and the responses of the I2C devices, are the chip's own, so they're
+    /// skipped.
+    pub fn replay(&self) {
+        let mut bus = VirtualI2cBus::new();
+        for event in &self.events {
+            if matches!(event, Event::Pin(event) if event.kind != PinEventKind::Input) {
+                continue;
+            }
+            let now = with_state(|state| state.nanos);
+            advance(Duration::from_nanos(event.nanos().saturating_sub(now)));
+            match event {
+                Event::Pin(event) => set_input(&event.pin, event.value),
+                Event::I2c(event) => match event.transfer {
+                    I2cTransfer::Connect { address, write, .. } => {
+                        bus.start(address, write);
+                    }
+                    I2cTransfer::Write { data, .. } => {
+                        bus.write(data);
+                    }
+                    I2cTransfer::Read { .. } => {
+                        bus.read();
+                    }
+                    I2cTransfer::Disconnect => bus.stop(),
+                },
+            }
+        }
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    type Err = ParseEventError;
+
+    /// Parses a recording, one event per line. Empty lines are skipped.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let events = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Recording { events })
+    }
+}
+
+/// Loads a recording saved by [`Recording::save`], and replays its inputs, see
+/// [`Recording::replay`].
+pub fn replay(path: impl AsRef<Path>) -> io::Result<()> {
+    Recording::load(path)?.replay();
+    Ok(())
+}
+
+// The `wokwi_chip_ll` functions, called by the crate instead of the simulator's.
+
+#[no_mangle]
+extern "C" fn pinInit(name: *const c_char, mode: u32) -> PinId {
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned();
+    with_state(|state| {
+        let index = match state.pins.iter().position(|pin| pin.name == name) {
+            Some(index) => index,
+            None => {
+                state.pins.push(MockPin {
+                    name,
+                    mode,
+                    value: 0,
+                    analog_input: 0.0,
+                    analog_output: 0.0,
+                    watch: None,
+                    refuse_watch: false,
+                });
+                state.pins.len() - 1
+            }
+        };
+        set_mode(&mut state.pins[index], mode);
+        index as PinId
+    })
+}
+
+fn set_mode(pin: &mut MockPin, mode: u32) {
+    pin.mode = mode;
+    match PinMode::try_from(mode) {
+        Ok(PinMode::OutputLow) => pin.value = 0,
+        Ok(PinMode::OutputHigh) => pin.value = 1,
+        Ok(PinMode::InputPullup) => pin.value = 1,
+        Ok(PinMode::InputPulldown) => pin.value = 0,
+        _ => {}
+    }
+}
+
+#[no_mangle]
+extern "C" fn pinMode(pin: PinId, mode: u32) {
+    with_state(|state| set_mode(&mut state.pins[pin as usize], mode));
+}
+
+#[no_mangle]
+extern "C" fn pinRead(pin: PinId) -> u32 {
+    with_state(|state| {
+        let value = state.pins[pin as usize].value;
+        record(state, PinEventKind::Read, pin as usize, value);
+        value
+    })
+}
+
+#[no_mangle]
+extern "C" fn pinWrite(pin: PinId, value: u32) {
+    with_state(|state| {
+        let value = (value != 0) as u32;
+        state.pins[pin as usize].value = value;
+        record(state, PinEventKind::Write, pin as usize, value);
+    });
+}
+
+#[no_mangle]
+extern "C" fn pinWatch(pin: PinId, watch_config: *const WatchConfig) -> bool {
+    let config = unsafe { &*watch_config };
+    with_state(|state| {
+        let pin = &mut state.pins[pin as usize];
+        if pin.watch.is_some() || pin.refuse_watch {
+            return false;
+        }
+        pin.watch = Some(MockWatch {
+            user_data: config.user_data,
+            edge: config.edge,
+            pin_change: config.pin_change,
+        });
+        true
+    })
+}
+
+#[no_mangle]
+extern "C" fn pinWatchStop(pin: PinId) {
+    with_state(|state| state.pins[pin as usize].watch = None);
+}
+
+#[no_mangle]
+extern "C" fn pinADCRead(pin: PinId) -> f32 {
+    with_state(|state| state.pins[pin as usize].analog_input)
+}
+
+#[no_mangle]
+extern "C" fn pinDACWrite(pin: PinId, value: f32) {
+    with_state(|state| state.pins[pin as usize].analog_output = value);
+}
+
+#[no_mangle]
+extern "C" fn getSimNanos() -> f64 {
+    with_state(|state| state.nanos as f64)
+}
+
+#[no_mangle]
+extern "C" fn timerInit(timer_config: *const TimerConfig) -> TimerId {
+    let config = unsafe { &*timer_config };
+    with_state(|state| {
+        state.timers.push(MockTimer {
+            user_data: config.user_data,
+            callback: config.callback,
+            deadline: None,
+            period: 0,
+            repeat: false,
+        });
+        (state.timers.len() - 1) as TimerId
+    })
+}
+
+#[no_mangle]
+extern "C" fn timerStart(timer: TimerId, micros: u32, repeat: bool) {
+    timerStartNanos(timer, micros as f64 * 1000.0, repeat);
+}
+
+#[no_mangle]
+extern "C" fn timerStartNanos(timer: TimerId, nanos: f64, repeat: bool) {
+    with_state(|state| {
+        let now = state.nanos;
+        let timer = &mut state.timers[timer as usize];
+        timer.period = nanos as u64;
+        timer.repeat = repeat;
+        timer.deadline = Some(now + timer.period);
+    });
+}
+
+#[no_mangle]
+extern "C" fn timerStop(timer: TimerId) {
+    with_state(|state| state.timers[timer as usize].deadline = None);
+}
+
+#[no_mangle]
+extern "C" fn uartInit(config: *const UARTConfig) -> UARTDevId {
+    let config = unsafe { &*config };
+    with_state(|state| {
+        state.uarts.push(MockUart {
+            user_data: config.user_data,
+            rx_data: config.rx_data,
+            write_done: config.write_done,
+            written: Vec::new(),
+            writing: false,
+            busy: false,
+        });
+        (state.uarts.len() - 1) as UARTDevId
+    })
+}
+
+#[no_mangle]
+extern "C" fn uartWrite(dev: UARTDevId, buffer: *const u8, count: u32) -> bool {
+    let bytes = unsafe { std::slice::from_raw_parts(buffer, count as usize) };
+    with_state(|state| {
+        let uart = &mut state.uarts[dev as usize];
+        if uart.writing || uart.busy {
+            return false;
+        }
+        uart.written.extend_from_slice(bytes);
+        uart.writing = true;
+        true
+    })
+}
+
+#[no_mangle]
+extern "C" fn i2cInit(config: *const I2CConfig) -> I2CDevId {
+    let config = unsafe { &*config };
+    with_state(|state| {
+        state.i2c_devices.push(MockI2C {
+            user_data: config.user_data,
+            address: config.address,
+            connect: config.connect,
+            read: config.read,
+            write: config.write,
+            disconnect: config.disconnect,
+        });
+        (state.i2c_devices.len() - 1) as I2CDevId
+    })
+}
+
+#[no_mangle]
+extern "C" fn spiInit(config: *const SPIConfig) -> SPIDevId {
+    let config = unsafe { &*config };
+    with_state(|state| {
+        state.spi_devices.push(MockSpi {
+            user_data: config.user_data,
+            done: config.done,
+            transfer: None,
+            stops: 0,
+        });
+        (state.spi_devices.len() - 1) as SPIDevId
+    })
+}
+
+#[no_mangle]
+extern "C" fn spiStart(dev: SPIDevId, buffer: *const u8, count: u32) {
+    with_state(|state| {
+        state.spi_devices[dev as usize].transfer = Some((buffer as *mut u8, count as usize, 0));
+    });
+}
+
+#[no_mangle]
+extern "C" fn spiStop(dev: SPIDevId) {
+    let done = with_state(|state| {
+        let device = &mut state.spi_devices[dev as usize];
+        device.stops += 1;
+        spi_done(device)
+    });
+    if let Some(done) = done {
+        done();
+    }
+}
+
+#[no_mangle]
+extern "C" fn attrInit(name: *const c_char, default_value: f64) -> AttrId {
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    with_state(|state| {
+        let value = state
+            .attribute_values
+            .iter()
+            .find(|(attr_name, _)| *attr_name == name)
+            .map_or(default_value, |&(_, value)| value);
+        state.attributes.push(value);
+        (state.attributes.len() - 1) as AttrId
+    })
+}
+
+#[no_mangle]
+extern "C" fn attrRead(attr: AttrId) -> u32 {
+    with_state(|state| state.attributes[attr as usize] as i64 as u32)
+}
+
+#[no_mangle]
+extern "C" fn attrReadFloat(attr: AttrId) -> f64 {
+    with_state(|state| state.attributes[attr as usize])
+}
+
+#[no_mangle]
+extern "C" fn framebufferInit(width: *mut u32, height: *mut u32) -> BufferId {
+    with_state(|state| {
+        let (w, h) = state.framebuffer_size;
+        unsafe {
+            *width = w;
+            *height = h;
+        }
+        state.framebuffer = vec![0; w as usize * h as usize * 4];
+        0
+    })
+}
+
+#[no_mangle]
+extern "C" fn bufferRead(_buffer: BufferId, offset: u32, data: *const u8, data_len: u32) -> u32 {
+    with_state(|state| {
+        let start = (offset as usize).min(state.framebuffer.len());
+        let end = (start + data_len as usize).min(state.framebuffer.len());
+        let data = unsafe { std::slice::from_raw_parts_mut(data as *mut u8, end - start) };
+        data.copy_from_slice(&state.framebuffer[start..end]);
+        (end - start) as u32
+    })
+}
+
+#[no_mangle]
+extern "C" fn bufferWrite(_buffer: BufferId, offset: u32, data: *const u8, data_len: u32) -> u32 {
+    with_state(|state| {
+        let start = (offset as usize).min(state.framebuffer.len());
+        let end = (start + data_len as usize).min(state.framebuffer.len());
+        let data = unsafe { std::slice::from_raw_parts(data, end - start) };
+        state.framebuffer[start..end].copy_from_slice(data);
+        (end - start) as u32
+    })
+}
+
+#[no_mangle]
+extern "C" fn debugPrint(message: *const c_char) {
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    with_state(|state| state.output.push_str(&message));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::{I2CAck, I2CDevice, I2CDeviceConfig};
+    use crate::pin::{Pin, PinMode, WatchEdge};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A chip with an I2C device that NACKs writes of 0xff and reads back the last byte written,
+    // and an inverter from IN to OUT.
+    fn chip() -> (I2CDevice, Pin) {
+        let last = Rc::new(Cell::new(0));
+        let write_last = last.clone();
+        let device = I2CDevice::new(
+            I2CDeviceConfig::builder(
+                0x42,
+                Pin::new("SCL", PinMode::Input),
+                Pin::new("SDA", PinMode::Input),
+            )
+            .on_connect(|address, _| address == 0x42)
+            .on_read(move || last.get())
+            .on_write(move |data| {
+                if data == 0xff {
+                    return I2CAck::Nack;
+                }
+                write_last.set(data);
+                I2CAck::Ack
+            })
+            .build(),
+        );
+        let output = Pin::new("OUT", PinMode::OutputLow);
+        let input = Pin::new("IN", PinMode::Input);
+        input.watch(WatchEdge::Both, move |_, value| output.write(!value));
+        (device, input)
+    }
+
+    // Drives the chip of `chip()` through a pin edge and a few I2C transactions.
+    fn drive(bus: &mut VirtualI2cBus) {
+        set_input("IN", PinValue::High);
+        advance(Duration::from_micros(5));
+        bus.start(0x42, true);
+        bus.write(0x5a);
+        bus.write(0xff);
+        bus.start(0x42, false);
+        advance(Duration::from_micros(5));
+        bus.read();
+        bus.stop();
+        bus.start(0x43, true);
+    }
+
+    #[test]
+    fn recordings_round_trip_i2c_traffic() {
+        let chip_state = chip();
+        let recorder = Recorder::start();
+        let mut bus = VirtualI2cBus::new();
+        drive(&mut bus);
+        let recording = recorder.stop();
+
+        let transfers: Vec<_> = recording
+            .i2c_events()
+            .iter()
+            .map(|event| event.transfer)
+            .collect();
+        assert_eq!(
+            transfers,
+            [
+                I2cTransfer::Connect {
+                    address: 0x42,
+                    write: true,
+                    ack: true
+                },
+                I2cTransfer::Write {
+                    data: 0x5a,
+                    ack: true
+                },
+                I2cTransfer::Write {
+                    data: 0xff,
+                    ack: false
+                },
+                I2cTransfer::Connect {
+                    address: 0x42,
+                    write: false,
+                    ack: true
+                },
+                I2cTransfer::Read { data: 0x5a },
+                I2cTransfer::Disconnect,
+                I2cTransfer::Connect {
+                    address: 0x43,
+                    write: true,
+                    ack: false
+                },
+            ]
+        );
+        assert_eq!(recording.i2c_events()[4].to_string(), "10000 i2c read 0x5a");
+
+        let path = std::env::temp_dir().join(format!(
+            "wokwi_chips_api_i2c_recording_{}.txt",
+            std::process::id()
+        ));
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.unwrap(), recording);
+
+        // Replaying on a new chip gives the same responses and writes.
+        drop(chip_state);
+        reset();
+        let _chip = chip();
+        let recorder = Recorder::start();
+        recording.replay();
+        let replayed = recorder.stop();
+        assert_eq!(replayed.i2c_events(), recording.i2c_events());
+        assert_eq!(replayed.writes(), recording.writes());
+        assert_eq!(replayed.writes().len(), 1);
+    }
+
+    #[test]
+    fn malformed_i2c_events_are_rejected() {
+        for line in [
+            "10 i2c connect 0x42 write maybe",
+            "10 i2c write 0x100 ack",
+            "10 i2c read 5a",
+            "10 i2c disconnect now",
+            "i2c disconnect",
+        ] {
+            assert_eq!(line.parse::<Event>(), Err(ParseEventError::new(line)));
+        }
+        assert_eq!(
+            "10 i2c connect 0x142 read nack".parse::<Event>(),
+            Ok(Event::I2c(I2cEvent {
+                nanos: 10,
+                transfer: I2cTransfer::Connect {
+                    address: 0x142,
+                    write: false,
+                    ack: false
+                }
+            }))
+        );
+    }
+}
@@ -1,7 +1,14 @@
 // SPDX-License-Identifier: MIT
 // Author: Uri Shaked
 
-use std::ffi::{c_void, CString};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::ffi::{c_void, CString, NulError};
+use std::future::Future;
+use std::ops::Range;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
 
 use wokwi_chip_ll::{
     pinInit, pinMode, pinRead, pinWatch, pinWatchStop, pinWrite, PinId, WatchConfig, ANALOG, BOTH,
@@ -11,7 +18,10 @@ use wokwi_chip_ll::{
 
 use std::boxed::Box;
 
-#[derive(Copy, Clone)]
+use crate::time::Instant;
+use crate::timer::Timer;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PinMode {
     Input = INPUT as isize,
     Output = OUTPUT as isize,
@@ -22,12 +32,97 @@ pub enum PinMode {
     OutputHigh = OUTPUT_HIGH as isize,
 }
 
-#[derive(Debug)]
+/// Prints the name of the mode, e.g. `InputPullup`.
+///
+/// ```rust
+/// use wokwi_chips_api::pin::PinMode;
+///
+/// assert_eq!(PinMode::InputPullup.to_string(), "InputPullup");
+/// assert_eq!(format!("{}", PinMode::OutputHigh), "OutputHigh");
+/// ```
+impl std::fmt::Display for PinMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Error returned when converting a raw value that isn't one of the `wokwi_chip_ll` pin mode
+/// constants into a [`PinMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPinMode(pub u32);
+
+impl std::fmt::Display for InvalidPinMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid pin mode: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidPinMode {}
+
+/// Converts one of the raw `wokwi_chip_ll` pin mode constants (e.g. an attribute value) into a
+/// [`PinMode`].
+///
+/// ```rust
+/// use wokwi_chips_api::pin::{InvalidPinMode, PinMode};
+///
+/// assert_eq!(PinMode::try_from(2), Ok(PinMode::InputPullup));
+/// assert_eq!(PinMode::try_from(17), Ok(PinMode::OutputHigh));
+/// assert_eq!(PinMode::try_from(5), Err(InvalidPinMode(5)));
+/// ```
+impl TryFrom<u32> for PinMode {
+    type Error = InvalidPinMode;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            INPUT => Ok(PinMode::Input),
+            OUTPUT => Ok(PinMode::Output),
+            INPUT_PULLUP => Ok(PinMode::InputPullup),
+            INPUT_PULLDOWN => Ok(PinMode::InputPulldown),
+            ANALOG => Ok(PinMode::Analog),
+            OUTPUT_LOW => Ok(PinMode::OutputLow),
+            OUTPUT_HIGH => Ok(PinMode::OutputHigh),
+            _ => Err(InvalidPinMode(value)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PinValue {
     Low = LOW as isize,
     High = HIGH as isize,
 }
 
+impl PinValue {
+    fn from_raw(value: u32) -> Self {
+        PinValue::from(value != 0)
+    }
+}
+
+/// Converts a `bool` to a pin value, where `true` means [`PinValue::High`].
+///
+/// ```rust
+/// use wokwi_chips_api::pin::PinValue;
+///
+/// assert_eq!(PinValue::from(true), PinValue::High);
+/// assert_eq!(!PinValue::from(true), PinValue::Low);
+/// assert!(!bool::from(PinValue::Low));
+/// ```
+impl From<bool> for PinValue {
+    fn from(value: bool) -> Self {
+        if value {
+            PinValue::High
+        } else {
+            PinValue::Low
+        }
+    }
+}
+
+impl From<PinValue> for bool {
+    fn from(value: PinValue) -> Self {
+        value == PinValue::High
+    }
+}
+
 impl std::ops::Not for PinValue {
     type Output = PinValue;
 
@@ -39,67 +134,431 @@ impl std::ops::Not for PinValue {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum WatchEdge {
     Rising = RISING as isize,
     Falling = FALLING as isize,
     Both = BOTH as isize,
 }
 
+/// Error returned by [`Pin::try_new`] when the pin name contains a NUL byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinInitError(NulError);
+
+impl std::fmt::Display for PinInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid pin name: {}", self.0)
+    }
+}
+
+impl std::error::Error for PinInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<NulError> for PinInitError {
+    fn from(error: NulError) -> Self {
+        PinInitError(error)
+    }
+}
+
+/// A chip pin.
+///
+/// If the pin is being watched (see [`Pin::watch`]), dropping it stops the watches it started and
+/// releases their callbacks. Watches owned by something else, e.g. a [`WatchGuard`] or an
+/// [`EdgeStream`], keep going. The `Pin` passed to a watch callback does not own any watch, so
+/// dropping it inside the callback has no effect, and watches added through it last until
+/// [`Pin::unwatch`].
 pub struct Pin {
     id: PinId,
+    // The listeners this `Pin` added, and removes when it's dropped. `None` for the `Pin` passed to
+    // watch callbacks, which doesn't own its watches.
+    listeners: Option<RefCell<Vec<ListenerId>>>,
+}
+
+/// A watch created by [`Pin::watch_scoped`]. Dropping the guard stops the watch.
+#[must_use = "the watch stops as soon as the guard is dropped"]
+pub struct WatchGuard {
+    pin_id: PinId,
+    listener_id: Option<ListenerId>,
+}
+
+impl WatchGuard {
+    /// Returns false if the simulator refused to watch the pin.
+    pub fn is_active(&self) -> bool {
+        self.listener_id.is_some()
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.listener_id.take() {
+            remove_listener(self.pin_id, id);
+        }
+    }
+}
+
+/// A future that resolves to the pin value on the next matching edge, see [`Pin::next_edge`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct EdgeFuture {
+    state: Rc<EdgeState>,
+    // Dropping the future removes the watch.
+    _guard: WatchGuard,
+}
+
+// Shared with the watch callback.
+#[derive(Default)]
+struct EdgeState {
+    value: Cell<Option<PinValue>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Future for EdgeFuture {
+    type Output = PinValue;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<PinValue> {
+        if let Some(value) = self.state.value.get() {
+            return Poll::Ready(value);
+        }
+        let mut waker = self.state.waker.borrow_mut();
+        match waker.as_mut() {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+/// The number of edges an [`EdgeStream`] queues until they're consumed.
+pub const EDGE_STREAM_CAPACITY: usize = 16;
+
+/// An async stream of the pin values on every matching edge, see [`Pin::edge_stream`].
+///
+/// The edges are queued until they're consumed, up to [`EDGE_STREAM_CAPACITY`]. When the queue
+/// is full, the oldest edge is dropped to make room for the new one, so the stream always has the
+/// most recent edges, and [`EdgeStream::dropped`] counts the edges that were lost.
+#[must_use = "streams do nothing unless polled"]
+pub struct EdgeStream {
+    state: Rc<EdgeStreamState>,
+    // Dropping the stream removes the watch.
+    _guard: WatchGuard,
+}
+
+// Shared with the watch callback.
+#[derive(Default)]
+struct EdgeStreamState {
+    queue: RefCell<VecDeque<PinValue>>,
+    dropped: Cell<usize>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl EdgeStream {
+    /// Returns a future that resolves to the next queued edge, waiting for one if the queue is
+    /// empty.
+    pub fn next_edge(&mut self) -> NextEdge<'_> {
+        NextEdge { stream: self }
+    }
+
+    /// Returns the next queued edge, or `Poll::Pending` and wakes the task when an edge comes.
+    /// The stream never ends, so this never returns `Poll::Ready(None)`; the signature is the one
+    /// of the `Stream` trait of the `futures` crate, for code that adapts the stream to it.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<PinValue>> {
+        if let Some(value) = self.state.queue.borrow_mut().pop_front() {
+            return Poll::Ready(Some(value));
+        }
+        let mut waker = self.state.waker.borrow_mut();
+        match waker.as_mut() {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+
+    /// Returns the number of queued edges.
+    pub fn len(&self) -> usize {
+        self.state.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.queue.borrow().is_empty()
+    }
+
+    /// Returns the number of edges dropped because the queue was full.
+    pub fn dropped(&self) -> usize {
+        self.state.dropped.get()
+    }
+}
+
+/// A future that resolves to the next edge of an [`EdgeStream`], see [`EdgeStream::next_edge`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct NextEdge<'a> {
+    stream: &'a mut EdgeStream,
+}
+
+impl Future for NextEdge<'_> {
+    type Output = PinValue;
+
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<PinValue> {
+        match self.stream.poll_next(cx) {
+            Poll::Ready(Some(value)) => Poll::Ready(value),
+            _ => Poll::Pending,
+        }
+    }
 }
 
 type WatchCallback = Box<dyn FnMut(Pin, PinValue) + 'static>;
 
+type ListenerId = usize;
+
 struct PinListener {
+    id: ListenerId,
     pin_id: PinId,
+    edge: WatchEdge,
+    // One-shot listeners are removed after their callback runs.
+    once: bool,
+    // Taken out while the callback runs, so that the callback is free to access the registry.
+    callback: Option<WatchCallback>,
+}
+
+impl WatchEdge {
+    // The same edge, seen through an inverter.
+    fn inverted(self) -> Self {
+        match self {
+            WatchEdge::Rising => WatchEdge::Falling,
+            WatchEdge::Falling => WatchEdge::Rising,
+            WatchEdge::Both => WatchEdge::Both,
+        }
+    }
+
+    fn matches(self, value: PinValue) -> bool {
+        match self {
+            WatchEdge::Rising => value == PinValue::High,
+            WatchEdge::Falling => value == PinValue::Low,
+            WatchEdge::Both => true,
+        }
+    }
+}
+
+thread_local! {
+    // This is a global registry of all the pins that have a watch set on them, so that we can keep
+    // the Rust callbacks alive as long as the watch is active. Chips run single-threaded inside the
+    // simulator, so a thread-local `RefCell` is all the synchronization we need.
+    static CALLBACK_REGISTRY: RefCell<Vec<PinListener>> = const { RefCell::new(Vec::new()) };
+    static NEXT_LISTENER_ID: Cell<ListenerId> = const { Cell::new(0) };
+}
+
+/// Adds a listener for the given pin, and returns its id and whether it is the first listener on
+/// that pin.
+fn register_listener(
+    pin_id: PinId,
+    edge: WatchEdge,
+    once: bool,
     callback: WatchCallback,
+) -> (ListenerId, bool) {
+    let id = NEXT_LISTENER_ID.get();
+    NEXT_LISTENER_ID.set(id + 1);
+    CALLBACK_REGISTRY.with_borrow_mut(|registry| {
+        let first = !registry.iter().any(|listener| listener.pin_id == pin_id);
+        registry.push(PinListener {
+            id,
+            pin_id,
+            edge,
+            once,
+            callback: Some(callback),
+        });
+        (id, first)
+    })
 }
 
-// This is a global registry of all the pins that have a watch set on them, so that we can keep the
-// Rust callbacks alive as long as the watch is active.
-static mut CALLBACK_REGISTRY: Vec<PinListener> = Vec::new();
+fn unregister_listeners(pin_id: PinId) {
+    CALLBACK_REGISTRY.with_borrow_mut(|registry| {
+        registry.retain(|listener| listener.pin_id != pin_id);
+    });
+}
 
-extern "C" fn pin_change_trampoline(_user_data: *mut c_void, pin_id: PinId, value: u32) {
-    let callback = unsafe {
-        CALLBACK_REGISTRY
-            .iter_mut()
-            .find(|listener| listener.pin_id == pin_id)
-            .map(|listener| &mut listener.callback)
+/// Removes a single listener, and stops watching the pin if it was the last one.
+fn remove_listener(pin_id: PinId, id: ListenerId) {
+    let last = CALLBACK_REGISTRY.with_borrow_mut(|registry| {
+        let len = registry.len();
+        registry.retain(|listener| listener.id != id);
+        registry.len() < len && !registry.iter().any(|listener| listener.pin_id == pin_id)
+    });
+    if last {
+        unsafe {
+            pinWatchStop(pin_id);
+        }
+    }
+}
+
+/// Registers a listener and starts watching the pin if needed. Returns `None` if the simulator
+/// refused to watch the pin.
+fn add_listener(
+    pin_id: PinId,
+    edge: WatchEdge,
+    once: bool,
+    callback: WatchCallback,
+) -> Option<ListenerId> {
+    let (id, first) = register_listener(pin_id, edge, once, callback);
+    if !first {
+        // The pin is already being watched for both edges, and the trampoline dispatches to every
+        // listener.
+        return Some(id);
+    }
+
+    let watch_config = WatchConfig {
+        // Listeners are looked up by pin id, so the `Pin` itself is free to move after this call.
+        user_data: std::ptr::null(),
+        edge: WatchEdge::Both as u32,
+        pin_change: pin_change_trampoline as *const c_void,
     };
 
-    if callback.is_none() {
-        return;
+    if unsafe { pinWatch(pin_id, &watch_config) } {
+        Some(id)
+    } else {
+        unregister_listeners(pin_id);
+        None
     }
+}
 
-    callback.unwrap()(
-        Pin { id: pin_id },
-        if value == 0 {
-            PinValue::Low
+fn matching_listeners(pin_id: PinId, value: PinValue) -> Vec<ListenerId> {
+    CALLBACK_REGISTRY.with_borrow(|registry| {
+        registry
+            .iter()
+            .filter(|listener| listener.pin_id == pin_id && listener.edge.matches(value))
+            .map(|listener| listener.id)
+            .collect()
+    })
+}
+
+/// Takes the callback out of the listener, along with the listener's `once` flag.
+fn take_callback(id: ListenerId) -> Option<(WatchCallback, bool)> {
+    CALLBACK_REGISTRY.with_borrow_mut(|registry| {
+        let listener = registry.iter_mut().find(|listener| listener.id == id)?;
+        Some((listener.callback.take()?, listener.once))
+    })
+}
+
+fn restore_callback(id: ListenerId, callback: WatchCallback) {
+    CALLBACK_REGISTRY.with_borrow_mut(|registry| {
+        // If the listener was removed while the callback was running, the callback is dropped here.
+        if let Some(listener) = registry.iter_mut().find(|listener| listener.id == id) {
+            listener.callback = Some(callback);
+        }
+    });
+}
+
+extern "C" fn pin_change_trampoline(_user_data: *mut c_void, pin_id: PinId, value: u32) {
+    let value = PinValue::from_raw(value);
+    for id in matching_listeners(pin_id, value) {
+        let Some((mut callback, once)) = take_callback(id) else {
+            continue;
+        };
+        callback(Pin::from_id(pin_id), value);
+        if once {
+            // Removing the listener only now, after the callback returned, keeps the callback
+            // alive while it runs.
+            remove_listener(pin_id, id);
         } else {
-            PinValue::High
-        },
-    );
+            restore_callback(id, callback);
+        }
+    }
 }
 
 impl Pin {
+    /// Initializes the pin with the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` contains a NUL byte. Use [`Pin::try_new`] if the name comes from user
+    /// input.
     pub fn new(name: &str, mode: PinMode) -> Self {
-        let c_name = CString::new(name).unwrap();
+        Self::try_new(name, mode).unwrap()
+    }
+
+    /// Initializes the pin with the given name, failing if the name contains a NUL byte.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// assert!(Pin::try_new("A\0B", PinMode::Input).is_err());
+    /// ```
+    pub fn try_new(name: &str, mode: PinMode) -> Result<Self, PinInitError> {
+        let c_name = CString::new(name)?;
         let id = unsafe { pinInit(c_name.as_ptr(), mode as u32) };
 
-        Self { id }
+        Ok(Self {
+            id,
+            listeners: Some(RefCell::new(Vec::new())),
+        })
     }
 
-    pub fn read(&self) -> PinValue {
-        unsafe {
-            if pinRead(self.id) == 0 {
-                PinValue::Low
-            } else {
-                PinValue::High
-            }
+    pub fn new_input(name: &str) -> Self {
+        Self::new(name, PinMode::Input)
+    }
+
+    pub fn new_input_pullup(name: &str) -> Self {
+        Self::new(name, PinMode::InputPullup)
+    }
+
+    pub fn new_input_pulldown(name: &str) -> Self {
+        Self::new(name, PinMode::InputPulldown)
+    }
+
+    pub fn new_output(name: &str) -> Self {
+        Self::new(name, PinMode::Output)
+    }
+
+    pub fn new_analog(name: &str) -> Self {
+        Self::new(name, PinMode::Analog)
+    }
+
+    /// Initializes a group of pins named `prefix` followed by each index in `range`.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    ///
+    /// // D0, D1, ..., D7
+    /// let data = Pin::array("D", 0..8, PinMode::Input).unwrap();
+    /// ```
+    pub fn array(
+        prefix: &str,
+        range: Range<usize>,
+        mode: PinMode,
+    ) -> Result<Vec<Pin>, PinInitError> {
+        range
+            .map(|index| Self::try_new(&format!("{}{}", prefix, index), mode))
+            .collect()
+    }
+
+    pub(crate) fn from_id(id: PinId) -> Self {
+        Self {
+            id,
+            listeners: None,
         }
     }
 
+    pub fn read(&self) -> PinValue {
+        PinValue::from_raw(unsafe { pinRead(self.id) })
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.read() == PinValue::High
+    }
+
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+
+    /// Reads the pin value as a `bool`, where `true` means [`PinValue::High`].
+    pub fn read_bool(&self) -> bool {
+        self.is_high()
+    }
+
     pub fn write(&self, value: PinValue) {
         unsafe {
             pinWrite(self.id, value as u32);
@@ -114,6 +573,27 @@ impl Pin {
         self.write(PinValue::High);
     }
 
+    /// Inverts the pin value.
+    ///
+    /// The new value is based on what [`Pin::read`] returns, which for output pins is the value
+    /// the chip is driving. For input pins, this writes the inverse of the value currently on the
+    /// wire.
+    pub fn toggle(&self) {
+        self.write(!self.read());
+    }
+
+    /// Drives the pin to `value` for `duration`, then drives it to the opposite value.
+    ///
+    /// Returns immediately; the pin is restored by a timer. The restore happens even if the pin
+    /// was written to (or pulsed again) in the meantime, and overrides whatever value was written.
+    pub fn pulse(&self, value: PinValue, duration: Duration) {
+        self.write(value);
+        let pin_id = self.id;
+        Timer::once(duration, move || {
+            Pin::from_id(pin_id).write(!value);
+        });
+    }
+
     pub fn set_mode(&mut self, mode: PinMode) {
         unsafe {
             pinMode(self.id, mode as u32);
@@ -124,29 +604,1027 @@ impl Pin {
         self.id
     }
 
+    /// Calls `callback` whenever the pin value changes on the given `edge`.
+    ///
+    /// A pin can have several callbacks, e.g. one for the rising edge and another one for the
+    /// falling edge. They are called in the order they were added.
+    ///
+    /// The callbacks keep firing until [`Pin::unwatch`] is called or this `Pin` is dropped. They
+    /// are stored on the heap, so the `Pin` can be moved (e.g. into a struct field) while the
+    /// watch is active.
+    ///
+    /// Returns false if the simulator refused to watch the pin.
     pub fn watch<F>(&self, edge: WatchEdge, callback: F) -> bool
     where
         F: FnMut(Pin, PinValue) + 'static,
     {
-        let watch_config = WatchConfig {
-            user_data: self as *const _ as *const c_void,
-            edge: edge as u32,
-            pin_change: pin_change_trampoline as *const c_void,
-        };
+        self.own_listener(add_listener(self.id, edge, false, Box::new(callback)))
+    }
 
-        unsafe {
-            CALLBACK_REGISTRY.push(PinListener {
+    // Records a listener added by this `Pin`, to remove it on drop.
+    fn own_listener(&self, listener_id: Option<ListenerId>) -> bool {
+        if let (Some(listeners), Some(id)) = (&self.listeners, listener_id) {
+            listeners.borrow_mut().push(id);
+        }
+        listener_id.is_some()
+    }
+
+    /// Watches both edges, calling `callback` with the previous and the new pin value.
+    pub fn watch_transitions<F>(&self, mut callback: F) -> bool
+    where
+        F: FnMut(PinValue, PinValue) + 'static,
+    {
+        let last_value = Cell::new(self.read());
+        self.watch(WatchEdge::Both, move |_, value| {
+            callback(last_value.replace(value), value);
+        })
+    }
+
+    /// Like [`Pin::watch`], but calls `callback` only for the first matching edge, and then stops
+    /// watching.
+    pub fn watch_once<F>(&self, edge: WatchEdge, callback: F) -> bool
+    where
+        F: FnOnce(Pin, PinValue) + 'static,
+    {
+        let mut callback = Some(callback);
+        self.own_listener(add_listener(
+            self.id,
+            edge,
+            true,
+            Box::new(move |pin, value| {
+                if let Some(callback) = callback.take() {
+                    callback(pin, value);
+                }
+            }),
+        ))
+    }
+
+    /// Like [`Pin::watch`], but the watch is tied to the returned guard instead of the pin: the
+    /// callback keeps firing as long as the guard is alive, and dropping the guard is the only way
+    /// to stop it (besides [`Pin::unwatch`], which stops all the watches on the pin).
+    ///
+    /// If the simulator refused to watch the pin, the returned guard is inactive.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+    ///
+    /// let ready = Pin::new("READY", PinMode::Input);
+    /// {
+    ///     let _guard = ready.watch_scoped(WatchEdge::Rising, |_, _| {
+    ///         // Only called while the guard is in scope
+    ///     });
+    ///     // ... setup sequence ...
+    /// }
+    /// ```
+    pub fn watch_scoped<F>(&self, edge: WatchEdge, callback: F) -> WatchGuard
+    where
+        F: FnMut(Pin, PinValue) + 'static,
+    {
+        WatchGuard {
+            pin_id: self.id,
+            listener_id: add_listener(self.id, edge, false, Box::new(callback)),
+        }
+    }
+
+    /// Returns a future that resolves to the pin value on the next `edge`, for async chip code
+    /// (see [`executor`](crate::executor)).
+    ///
+    /// The watch starts when this is called, not when the future is first polled, so an edge
+    /// that comes in between isn't missed. Dropping the future before the edge removes the watch,
+    /// and the edge is then ignored. If the simulator refuses to watch the pin, the future never
+    /// resolves.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinValue, WatchEdge};
+    ///
+    /// async fn wait_for_start(cs: &Pin, clock: &Pin) -> PinValue {
+    ///     cs.next_edge(WatchEdge::Falling).await;
+    ///     clock.next_edge(WatchEdge::Rising).await
+    /// }
+    /// ```
+    pub fn next_edge(&self, edge: WatchEdge) -> EdgeFuture {
+        let state = Rc::new(EdgeState::default());
+        let watch_state = state.clone();
+        let listener_id = add_listener(
+            self.id,
+            edge,
+            true,
+            Box::new(move |_, value| {
+                watch_state.value.set(Some(value));
+                if let Some(waker) = watch_state.waker.take() {
+                    waker.wake();
+                }
+            }),
+        );
+        EdgeFuture {
+            state,
+            _guard: WatchGuard {
+                pin_id: self.id,
+                listener_id,
+            },
+        }
+    }
+
+    /// Returns an async stream of the pin values on every `edge`, for async chip code that
+    /// handles edges in a loop rather than in a callback. Dropping the stream stops the watch.
+    ///
+    /// If the simulator refuses to watch the pin, the stream never yields.
+    ///
+    /// Example, collecting the first 8 bits clocked in:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+    ///
+    /// async fn read_byte(clock: &Pin, data: &Pin) -> u8 {
+    ///     let mut edges = clock.edge_stream(WatchEdge::Rising);
+    ///     let mut byte = 0;
+    ///     for _ in 0..8 {
+    ///         edges.next_edge().await;
+    ///         byte = byte << 1 | data.is_high() as u8;
+    ///     }
+    ///     byte
+    /// }
+    /// ```
+    pub fn edge_stream(&self, edge: WatchEdge) -> EdgeStream {
+        let state = Rc::new(EdgeStreamState::default());
+        let watch_state = state.clone();
+        let listener_id = add_listener(
+            self.id,
+            edge,
+            false,
+            Box::new(move |_, value| {
+                let mut queue = watch_state.queue.borrow_mut();
+                if queue.len() == EDGE_STREAM_CAPACITY {
+                    queue.pop_front();
+                    watch_state.dropped.set(watch_state.dropped.get() + 1);
+                }
+                queue.push_back(value);
+                drop(queue);
+                if let Some(waker) = watch_state.waker.take() {
+                    waker.wake();
+                }
+            }),
+        );
+        EdgeStream {
+            state,
+            _guard: WatchGuard {
                 pin_id: self.id,
-                callback: Box::new(callback),
-            });
+                listener_id,
+            },
         }
+    }
+
+    /// Like [`Pin::watch`], but only calls `callback` once the pin value has been stable for
+    /// `debounce`.
+    ///
+    /// Every change restarts the debounce interval, so a burst of bounces collapses into a single
+    /// call with the value the pin settled on. Bursts that end with the pin back at its previous
+    /// stable value don't call `callback` at all.
+    pub fn watch_debounced<F>(&self, edge: WatchEdge, debounce: Duration, mut callback: F) -> bool
+    where
+        F: FnMut(Pin, PinValue) + 'static,
+    {
+        let pin_id = self.id;
+        let stable_value = Cell::new(self.read());
+        let timer = Timer::new(move || {
+            let value = Pin::from_id(pin_id).read();
+            if value == stable_value.get() {
+                return;
+            }
+            stable_value.set(value);
+            if edge.matches(value) {
+                callback(Pin::from_id(pin_id), value);
+            }
+        });
+
+        // The timer lives in the watch callback, so it goes away together with the watch.
+        self.watch(WatchEdge::Both, move |_, _| {
+            timer.start(debounce, false);
+        })
+    }
+
+    /// Like [`Pin::watch`], but ignores glitches: an edge is only reported once the new level has
+    /// lasted for `min_width`, as measured on the simulation clock, so pulses shorter than that
+    /// never call `callback`. Reported edges are `min_width` late.
+    ///
+    /// This is not the same as [`Pin::watch_debounced`], although both wait for the pin to be
+    /// stable. Debouncing is for mechanical contacts, which bounce for several milliseconds when
+    /// they switch: it waits for the bounces to stop, and reports the value the contact settled
+    /// on, once per press. A glitch filter is for logic signals that don't bounce, but may carry
+    /// the odd narrow spike: `min_width` is chosen below the shortest real pulse, often under a
+    /// microsecond, so every real edge is still reported, and only the spikes are dropped.
+    /// Changes closer together than `min_width` are all taken as glitches.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+    ///
+    /// let clock = Pin::new("CLK", PinMode::Input);
+    /// clock.watch_filtered(WatchEdge::Rising, Duration::from_nanos(200), |_, _| {
+    ///     // A real rising edge, high for at least 200 ns
+    /// });
+    /// ```
+    pub fn watch_filtered<F>(&self, edge: WatchEdge, min_width: Duration, mut callback: F) -> bool
+    where
+        F: FnMut(Pin, PinValue) + 'static,
+    {
+        let pin_id = self.id;
+        let last_edge = Rc::new(Cell::new(Instant::now()));
+        let reported_value = Cell::new(self.read());
+        let timer_last_edge = last_edge.clone();
+        let timer = Timer::new(move || {
+            // A timer started for an earlier edge may still fire after a later one.
+            if timer_last_edge.get().elapsed() < min_width {
+                return;
+            }
+            let value = Pin::from_id(pin_id).read();
+            if reported_value.replace(value) != value && edge.matches(value) {
+                callback(Pin::from_id(pin_id), value);
+            }
+        });
+
+        // The timer lives in the watch callback, so it goes away together with the watch.
+        self.watch(WatchEdge::Both, move |_, _| {
+            last_edge.set(Instant::now());
+            timer.start(min_width, false);
+        })
+    }
 
-        unsafe { pinWatch(self.id, &watch_config) }
+    // Ties the watch of `guard` to this pin, as if it was added by `Pin::watch`.
+    pub(crate) fn keep_watch(&self, mut guard: WatchGuard) {
+        debug_assert_eq!(guard.pin_id, self.id);
+        self.own_listener(guard.listener_id.take());
     }
 
+    /// Stops watching the pin and releases all of its watch callbacks.
     pub fn unwatch(&self) {
         unsafe {
             pinWatchStop(self.id);
         }
+        unregister_listeners(self.id);
+        if let Some(listeners) = &self.listeners {
+            listeners.borrow_mut().clear();
+        }
+    }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        // Listeners that are already gone (e.g. a fired `watch_once`) are skipped, and the pin
+        // watch only stops once no listener is left, whoever added it.
+        if let Some(listeners) = self.listeners.take() {
+            for id in listeners.into_inner() {
+                remove_listener(self.id, id);
+            }
+        }
+    }
+}
+
+/// Error returned by [`PinBuilder::try_build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinBuildError {
+    /// The pin name contains a NUL byte.
+    InvalidName(PinInitError),
+    /// An initial value was set for a pin that isn't an output.
+    InitialValueNotOutput(PinMode),
+}
+
+impl std::fmt::Display for PinBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PinBuildError::InvalidName(error) => error.fmt(f),
+            PinBuildError::InitialValueNotOutput(mode) => {
+                write!(f, "an initial value needs an output mode, not {}", mode)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PinBuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PinBuildError::InvalidName(error) => Some(error),
+            PinBuildError::InitialValueNotOutput(_) => None,
+        }
+    }
+}
+
+impl From<PinInitError> for PinBuildError {
+    fn from(error: PinInitError) -> Self {
+        PinBuildError::InvalidName(error)
+    }
+}
+
+/// Builder for a [`Pin`] with a mode, an initial value and a watch, see [`Pin::builder`].
+pub struct PinBuilder<'a> {
+    name: &'a str,
+    mode: PinMode,
+    initial: Option<PinValue>,
+    watch: Option<(WatchEdge, WatchCallback)>,
+}
+
+impl Pin {
+    /// Returns a builder for a pin, which is an [`PinMode::Input`] unless a mode is set.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+    ///
+    /// let led = Pin::builder("LED").mode(PinMode::Output).initial(PinValue::High).build();
+    /// let button = Pin::builder("BTN")
+    ///     .mode(PinMode::InputPullup)
+    ///     .watch(WatchEdge::Falling, |_, _| println!("Button pressed"))
+    ///     .build();
+    /// ```
+    pub fn builder(name: &str) -> PinBuilder<'_> {
+        PinBuilder {
+            name,
+            mode: PinMode::Input,
+            initial: None,
+            watch: None,
+        }
+    }
+}
+
+impl PinBuilder<'_> {
+    pub fn mode(mut self, mode: PinMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the value of an output pin. The pin starts with this value, using
+    /// [`PinMode::OutputLow`] or [`PinMode::OutputHigh`], so there's no glitch on the pin.
+    pub fn initial(mut self, value: PinValue) -> Self {
+        self.initial = Some(value);
+        self
+    }
+
+    /// Watches the pin once it's created, see [`Pin::watch`].
+    pub fn watch<F>(mut self, edge: WatchEdge, callback: F) -> Self
+    where
+        F: FnMut(Pin, PinValue) + 'static,
+    {
+        self.watch = Some((edge, Box::new(callback)));
+        self
+    }
+
+    /// Creates the pin.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pin name contains a NUL byte, or an initial value was set for a pin that
+    /// isn't an output. Use [`PinBuilder::try_build`] to handle the errors instead.
+    pub fn build(self) -> Pin {
+        self.try_build().unwrap()
+    }
+
+    /// Creates the pin, or returns an error if the pin name contains a NUL byte, or an initial
+    /// value was set for a pin that isn't an output. The errors are checked before the pin is
+    /// initialized.
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinBuildError, PinMode, PinValue};
+    ///
+    /// let result = Pin::builder("IN").mode(PinMode::Input).initial(PinValue::High).try_build();
+    /// assert_eq!(result.err(), Some(PinBuildError::InitialValueNotOutput(PinMode::Input)));
+    /// ```
+    pub fn try_build(self) -> Result<Pin, PinBuildError> {
+        let mode = match (self.mode, self.initial) {
+            (_, None) => self.mode,
+            (PinMode::Output | PinMode::OutputLow | PinMode::OutputHigh, Some(PinValue::Low)) => {
+                PinMode::OutputLow
+            }
+            (PinMode::Output | PinMode::OutputLow | PinMode::OutputHigh, Some(PinValue::High)) => {
+                PinMode::OutputHigh
+            }
+            (mode, Some(_)) => return Err(PinBuildError::InitialValueNotOutput(mode)),
+        };
+        let pin = Pin::try_new(self.name, mode)?;
+        if let Some((edge, callback)) = self.watch {
+            pin.own_listener(add_listener(pin.id, edge, false, callback));
+        }
+        Ok(pin)
+    }
+}
+
+type ChangeCallback = Box<dyn FnMut(PinValue, PinValue) + 'static>;
+
+/// Builder for a watch that combines an edge, debouncing and one-shot watching, see
+/// [`Pin::watch_builder`].
+///
+/// All the options combine with each other:
+///
+/// - [`WatchBuilder::edge`] selects the edges that call the callback, [`WatchEdge::Both`] by
+///   default.
+/// - [`WatchBuilder::debounce`] waits for the pin to be stable before calling the callback, like
+///   [`Pin::watch_debounced`]. The edge is then the one of the settled value.
+/// - [`WatchBuilder::once`] only calls the callback for the first matching edge. The watch stays
+///   installed, without calling the callback again, until the guard is dropped.
+///
+/// [`WatchBuilder::on_change`] sets the callback, and must be called before
+/// [`WatchBuilder::build`].
+pub struct WatchBuilder {
+    pin_id: PinId,
+    edge: WatchEdge,
+    debounce: Option<Duration>,
+    once: bool,
+    callback: Option<ChangeCallback>,
+}
+
+impl Pin {
+    /// Returns a builder for a watch on the pin, see [`WatchBuilder`]. The watch is tied to the
+    /// guard returned by [`WatchBuilder::build`], like [`Pin::watch_scoped`].
+    ///
+    /// Example, reacting to the first press of a bouncy button:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+    ///
+    /// let button = Pin::new("BTN", PinMode::InputPullup);
+    /// let guard = button
+    ///     .watch_builder()
+    ///     .edge(WatchEdge::Falling)
+    ///     .debounce(Duration::from_millis(5))
+    ///     .once()
+    ///     .on_change(|old, new| println!("Button: {:?} -> {:?}", old, new))
+    ///     .build();
+    /// // Keep `guard` alive for as long as the watch should run.
+    /// ```
+    pub fn watch_builder(&self) -> WatchBuilder {
+        WatchBuilder {
+            pin_id: self.id,
+            edge: WatchEdge::Both,
+            debounce: None,
+            once: false,
+            callback: None,
+        }
+    }
+}
+
+impl WatchBuilder {
+    pub fn edge(mut self, edge: WatchEdge) -> Self {
+        self.edge = edge;
+        self
+    }
+
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Some(debounce);
+        self
+    }
+
+    pub fn once(mut self) -> Self {
+        self.once = true;
+        self
+    }
+
+    /// Sets the callback, which gets the previous and the new pin value.
+    pub fn on_change<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(PinValue, PinValue) + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Installs the watch. Dropping the returned guard stops it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no callback was set with [`WatchBuilder::on_change`].
+    pub fn build(self) -> WatchGuard {
+        let mut callback = self
+            .callback
+            .expect("the watch needs an on_change callback");
+        let pin_id = self.pin_id;
+        let edge = self.edge;
+        let once = self.once;
+        let fired = Cell::new(false);
+        let mut notify = move |old: PinValue, new: PinValue| {
+            if edge.matches(new) && !(once && fired.get()) {
+                fired.set(true);
+                callback(old, new);
+            }
+        };
+
+        let last_value = Cell::new(Pin::from_id(pin_id).read());
+        let watch_callback: WatchCallback = match self.debounce {
+            None => Box::new(move |_, value| notify(last_value.replace(value), value)),
+            Some(debounce) => {
+                let timer = Timer::new(move || {
+                    let value = Pin::from_id(pin_id).read();
+                    if value != last_value.get() {
+                        notify(last_value.replace(value), value);
+                    }
+                });
+                // The timer lives in the watch callback, so it goes away together with the watch.
+                Box::new(move |_, _| timer.start(debounce, false))
+            }
+        };
+        WatchGuard {
+            pin_id,
+            listener_id: add_listener(pin_id, WatchEdge::Both, false, watch_callback),
+        }
+    }
+}
+
+/// A pin with inverted logic, for active-low signals such as `RESET` or `CS`: the values it reads,
+/// writes and reports to watches are the logical values, the inverse of the level on the wire.
+/// So [`InvertedPin::set_high`] asserts the signal by driving the pin low.
+///
+/// Pin modes keep their electrical meaning, e.g. [`PinMode::OutputHigh`] still starts the pin at
+/// a high level, which for an active-low signal means deasserted.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pin::{InvertedPin, Pin, PinMode, WatchEdge};
+///
+/// let reset = InvertedPin::new(Pin::new("RESET", PinMode::Input));
+/// reset.watch(WatchEdge::Rising, |_, _| {
+///     // RESET went low: the reset is asserted
+/// });
+///
+/// let cs = InvertedPin::new(Pin::new("CS", PinMode::OutputHigh));
+/// cs.set_high();
+/// // ... talk to the device ...
+/// cs.set_low();
+/// ```
+pub struct InvertedPin {
+    pin: Pin,
+}
+
+impl InvertedPin {
+    pub fn new(pin: Pin) -> Self {
+        Self { pin }
+    }
+
+    /// Returns the logical value, i.e. [`PinValue::High`] when the pin is low.
+    pub fn read(&self) -> PinValue {
+        !self.pin.read()
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.read() == PinValue::High
+    }
+
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+
+    /// Writes the logical `value`, i.e. drives the pin low for [`PinValue::High`].
+    pub fn write(&self, value: PinValue) {
+        self.pin.write(!value);
+    }
+
+    pub fn set_low(&self) {
+        self.write(PinValue::Low);
+    }
+
+    pub fn set_high(&self) {
+        self.write(PinValue::High);
+    }
+
+    /// Inverts the pin value, see [`Pin::toggle`].
+    pub fn toggle(&self) {
+        self.pin.toggle();
+    }
+
+    pub fn set_mode(&mut self, mode: PinMode) {
+        self.pin.set_mode(mode);
+    }
+
+    /// Like [`Pin::watch`], with logical values: `edge` is the logical edge, so
+    /// [`WatchEdge::Rising`] fires when the pin goes low, and `callback` gets the logical value.
+    pub fn watch<F>(&self, edge: WatchEdge, mut callback: F) -> bool
+    where
+        F: FnMut(InvertedPin, PinValue) + 'static,
+    {
+        self.pin.watch(edge.inverted(), move |pin, value| {
+            callback(InvertedPin::new(pin), !value);
+        })
+    }
+
+    /// Like [`Pin::watch_scoped`], with logical values, see [`InvertedPin::watch`].
+    pub fn watch_scoped<F>(&self, edge: WatchEdge, mut callback: F) -> WatchGuard
+    where
+        F: FnMut(InvertedPin, PinValue) + 'static,
+    {
+        self.pin.watch_scoped(edge.inverted(), move |pin, value| {
+            callback(InvertedPin::new(pin), !value);
+        })
+    }
+
+    /// Returns the underlying pin, which reads and writes electrical levels.
+    pub fn pin(&self) -> &Pin {
+        &self.pin
+    }
+
+    pub fn into_inner(self) -> Pin {
+        self.pin
+    }
+}
+
+/// Declares a struct of named pins, and a `new()` function initializing them.
+///
+/// Each field is `name: Mode = "PIN"`, where `Mode` is a [`PinMode`] variant and `"PIN"` is the
+/// pin name in the chip's JSON definition.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pins;
+///
+/// pins! {
+///     pub struct ChipPins {
+///         led: Output = "LED",
+///         btn: InputPullup = "BTN",
+///     }
+/// }
+///
+/// let pins = ChipPins::new();
+/// pins.led.set_high();
+/// let pressed = pins.btn.is_low();
+/// ```
+#[macro_export]
+macro_rules! pins {
+  (
+      $(#[$meta:meta])*
+      $vis:vis struct $name:ident {
+          $($field:ident: $mode:ident = $pin:expr),* $(,)?
+      }
+  ) => {
+      $(#[$meta])*
+      $vis struct $name {
+          $(pub $field: $crate::pin::Pin,)*
+      }
+
+      impl $name {
+          /// Initializes all the pins.
+          #[allow(clippy::new_without_default)]
+          pub fn new() -> Self {
+              Self {
+                  $($field: $crate::pin::Pin::new($pin, $crate::pin::PinMode::$mode),)*
+              }
+          }
+      }
+  };
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    // Returns a counter and a watch callback incrementing it.
+    fn edge_counter() -> (Rc<Cell<u32>>, impl FnMut(Pin, PinValue) + 'static) {
+        let count = Rc::new(Cell::new(0));
+        let callback_count = count.clone();
+        (count, move |_, _| {
+            callback_count.set(callback_count.get() + 1)
+        })
+    }
+
+    fn registry_len() -> usize {
+        CALLBACK_REGISTRY.with_borrow(|registry| registry.len())
+    }
+
+    #[test]
+    fn watch_and_unwatch_register_and_unregister_listeners() {
+        let a = Pin::new("A", PinMode::Input);
+        let b = Pin::new("B", PinMode::Input);
+        let (a_edges, a_callback) = edge_counter();
+        let (b_edges, b_callback) = edge_counter();
+        assert!(a.watch(WatchEdge::Both, a_callback));
+        assert!(b.watch(WatchEdge::Both, b_callback));
+        assert_eq!(registry_len(), 2);
+
+        a.unwatch();
+        assert_eq!(registry_len(), 1);
+        assert!(!mock::is_watched("A"));
+        mock::set_input("A", PinValue::High);
+        mock::set_input("B", PinValue::High);
+        assert_eq!(a_edges.get(), 0);
+        assert_eq!(b_edges.get(), 1);
+
+        drop(b);
+        assert_eq!(registry_len(), 0);
+        assert!(!mock::is_watched("B"));
+    }
+
+    #[test]
+    fn a_callback_can_add_and_remove_watches() {
+        let pin = Pin::new("IN", PinMode::Input);
+        let (added_edges, added_callback) = edge_counter();
+        let mut added_callback = Some(added_callback);
+        pin.watch(WatchEdge::Rising, move |pin, _| {
+            // The registry isn't borrowed while this runs.
+            if let Some(callback) = added_callback.take() {
+                pin.watch(WatchEdge::Both, callback);
+            }
+        });
+        mock::set_input("IN", PinValue::High);
+        // Listeners are matched before the callbacks run, so the new one starts at the next edge.
+        assert_eq!(added_edges.get(), 0);
+        mock::set_input("IN", PinValue::Low);
+        assert_eq!(added_edges.get(), 1);
+
+        pin.watch(WatchEdge::Rising, |pin, _| pin.unwatch());
+        mock::set_input("IN", PinValue::High);
+        assert_eq!(registry_len(), 0);
+        mock::set_input("IN", PinValue::Low);
+        assert_eq!(added_edges.get(), 2);
+    }
+
+    #[test]
+    fn rewatching_after_unwatch_calls_the_new_callback() {
+        let pin = Pin::new("IN", PinMode::Input);
+        let (old_edges, old_callback) = edge_counter();
+        let (new_edges, new_callback) = edge_counter();
+        pin.watch(WatchEdge::Both, old_callback);
+        pin.unwatch();
+        pin.watch(WatchEdge::Both, new_callback);
+
+        mock::set_input("IN", PinValue::High);
+        assert_eq!(old_edges.get(), 0);
+        assert_eq!(new_edges.get(), 1);
+    }
+
+    #[test]
+    fn try_new_rejects_names_with_a_nul_byte() {
+        assert!(Pin::try_new("A\0B", PinMode::Input).is_err());
+        assert!(Pin::try_new("AB", PinMode::Input).is_ok());
+        assert_eq!(mock::pin_mode("AB"), PinMode::Input);
+    }
+
+    #[test]
+    fn watch_debounced_collapses_a_burst_into_one_call() {
+        let pin = Pin::new("BTN", PinMode::Input);
+        let values = Rc::new(RefCell::new(Vec::new()));
+        let watch_values = values.clone();
+        pin.watch_debounced(
+            WatchEdge::Both,
+            Duration::from_millis(5),
+            move |_, value| {
+                watch_values.borrow_mut().push(value);
+            },
+        );
+
+        // Bounces every 100 µs, settling high.
+        for value in [PinValue::High, PinValue::Low, PinValue::High, PinValue::Low] {
+            mock::set_input("BTN", value);
+            mock::advance(Duration::from_micros(100));
+        }
+        mock::set_input("BTN", PinValue::High);
+        mock::advance(Duration::from_micros(4900));
+        assert!(values.borrow().is_empty());
+        mock::advance(Duration::from_micros(100));
+        assert_eq!(*values.borrow(), [PinValue::High]);
+
+        // A burst that ends at the stable value doesn't call back.
+        mock::set_input("BTN", PinValue::Low);
+        mock::advance(Duration::from_micros(100));
+        mock::set_input("BTN", PinValue::High);
+        mock::advance(Duration::from_millis(10));
+        assert_eq!(*values.borrow(), [PinValue::High]);
+    }
+
+    #[test]
+    fn watch_once_fires_for_the_first_edge_only() {
+        let pin = Pin::new("READY", PinMode::Input);
+        let (edges, callback) = edge_counter();
+        pin.watch_once(WatchEdge::Rising, callback);
+
+        mock::set_input("READY", PinValue::High);
+        assert_eq!(edges.get(), 1);
+        assert_eq!(registry_len(), 0);
+        assert!(!mock::is_watched("READY"));
+        mock::set_input("READY", PinValue::Low);
+        mock::set_input("READY", PinValue::High);
+        assert_eq!(edges.get(), 1);
+    }
+
+    #[test]
+    fn dropping_a_pin_keeps_the_watches_it_does_not_own() {
+        let pin = Pin::new("IN", PinMode::Input);
+        let (pin_edges, pin_callback) = edge_counter();
+        let (guard_edges, guard_callback) = edge_counter();
+        pin.watch(WatchEdge::Both, pin_callback);
+        let guard = pin.watch_scoped(WatchEdge::Both, guard_callback);
+        let mut stream = pin.edge_stream(WatchEdge::Both);
+
+        drop(pin);
+        mock::set_input("IN", PinValue::High);
+        assert_eq!(pin_edges.get(), 0);
+        assert_eq!(guard_edges.get(), 1);
+        assert_eq!(stream.len(), 1);
+        assert!(mock::is_watched("IN"));
+
+        drop(guard);
+        assert!(mock::is_watched("IN"));
+        let waker = std::task::Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        assert_eq!(stream.poll_next(&mut cx), Poll::Ready(Some(PinValue::High)));
+        drop(stream);
+        assert!(!mock::is_watched("IN"));
+    }
+
+    #[test]
+    fn dropping_a_pin_after_a_fired_once_watch_keeps_other_watches() {
+        let pin = Pin::new("IN", PinMode::Input);
+        let (once_edges, mut once_callback) = edge_counter();
+        let (guard_edges, guard_callback) = edge_counter();
+        pin.watch_once(WatchEdge::Rising, move |pin, value| {
+            once_callback(pin, value)
+        });
+        mock::set_input("IN", PinValue::High);
+        let _guard = pin.watch_scoped(WatchEdge::Both, guard_callback);
+
+        drop(pin);
+        mock::set_input("IN", PinValue::Low);
+        assert_eq!(once_edges.get(), 1);
+        assert_eq!(guard_edges.get(), 1);
+        assert!(mock::is_watched("IN"));
+    }
+
+    #[test]
+    fn edge_stream_yields_each_matching_edge_in_a_task() {
+        let clock = Pin::new("CLK", PinMode::Input);
+        let data = Pin::new("DATA", PinMode::Input);
+        let byte = Rc::new(Cell::new(None));
+        let task_byte = byte.clone();
+        crate::executor::spawn(async move {
+            let mut edges = clock.edge_stream(WatchEdge::Rising);
+            let mut value = 0u8;
+            for _ in 0..8 {
+                assert_eq!(edges.next_edge().await, PinValue::High);
+                value = value << 1 | data.is_high() as u8;
+            }
+            task_byte.set(Some(value));
+        });
+        crate::executor::run();
+
+        for bit in (0..8).rev() {
+            mock::set_input("DATA", PinValue::from(0xa5u8 >> bit & 1 != 0));
+            mock::set_input("CLK", PinValue::High);
+            mock::set_input("CLK", PinValue::Low);
+        }
+        assert_eq!(byte.get(), Some(0xa5));
+        assert!(!mock::is_watched("CLK"));
+    }
+
+    #[test]
+    fn a_full_edge_stream_drops_the_oldest_edges() {
+        let pin = Pin::new("IN", PinMode::Input);
+        let mut stream = pin.edge_stream(WatchEdge::Both);
+        let edges = EDGE_STREAM_CAPACITY + 3;
+        for edge in 0..edges {
+            mock::set_input("IN", PinValue::from(edge % 2 == 0));
+        }
+        assert_eq!(stream.len(), EDGE_STREAM_CAPACITY);
+        assert_eq!(stream.dropped(), 3);
+
+        // The 4th edge, a falling one, is the oldest one left.
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(stream.poll_next(&mut cx), Poll::Ready(Some(PinValue::Low)));
+        for _ in 1..EDGE_STREAM_CAPACITY {
+            assert!(stream.poll_next(&mut cx).is_ready());
+        }
+        assert!(stream.is_empty());
+        assert_eq!(stream.poll_next(&mut cx), Poll::Pending);
+    }
+
+    crate::pins! {
+        /// Attributes and doc comments are kept.
+        #[allow(dead_code)]
+        pub(crate) struct TestPins {
+            led: OutputLow = "LED",
+            btn: InputPullup = "BTN",
+            data: Input = "DATA",
+        }
+    }
+
+    #[test]
+    fn pins_macro_declares_and_initializes_the_pins() {
+        let pins = TestPins::new();
+        assert_eq!(mock::pin_mode("LED"), PinMode::OutputLow);
+        assert_eq!(mock::pin_mode("BTN"), PinMode::InputPullup);
+        assert_eq!(mock::pin_mode("DATA"), PinMode::Input);
+        pins.led.set_high();
+        assert_eq!(mock::pin_value("LED"), PinValue::High);
+        assert!(pins.btn.is_high());
+    }
+
+    // Watches "IN" through a glitch filter, returning the reported values.
+    fn filtered_edges(min_width: Duration) -> (Pin, Rc<RefCell<Vec<PinValue>>>) {
+        let input = Pin::new("IN", PinMode::Input);
+        let edges = Rc::new(RefCell::new(Vec::new()));
+        let watch_edges = edges.clone();
+        assert!(
+            input.watch_filtered(WatchEdge::Both, min_width, move |_, value| {
+                watch_edges.borrow_mut().push(value)
+            })
+        );
+        (input, edges)
+    }
+
+    #[test]
+    fn watch_filtered_ignores_a_glitch_shorter_than_the_threshold() {
+        let (_input, edges) = filtered_edges(Duration::from_micros(1));
+        mock::set_input("IN", PinValue::High);
+        mock::advance(Duration::from_nanos(999));
+        mock::set_input("IN", PinValue::Low);
+        mock::advance(Duration::from_micros(10));
+        assert!(edges.borrow().is_empty());
+
+        mock::set_input("IN", PinValue::High);
+        mock::advance(Duration::from_micros(10));
+        assert_eq!(*edges.borrow(), [PinValue::High]);
+    }
+
+    #[test]
+    fn watch_filtered_reports_a_pulse_exactly_at_the_threshold() {
+        let (_input, edges) = filtered_edges(Duration::from_nanos(200));
+        mock::set_input("IN", PinValue::High);
+        mock::advance(Duration::from_nanos(199));
+        assert!(edges.borrow().is_empty());
+        mock::advance(Duration::from_nanos(1));
+        assert_eq!(*edges.borrow(), [PinValue::High]);
+        mock::set_input("IN", PinValue::Low);
+        mock::advance(Duration::from_nanos(200));
+        assert_eq!(*edges.borrow(), [PinValue::High, PinValue::Low]);
+    }
+
+    #[test]
+    fn watch_filtered_waits_for_the_last_of_close_changes() {
+        let (_input, edges) = filtered_edges(Duration::from_nanos(500));
+        // Changes closer together than the threshold are all glitches, until the level lasts.
+        for _ in 0..5 {
+            mock::set_input("IN", PinValue::High);
+            mock::advance(Duration::from_nanos(300));
+            mock::set_input("IN", PinValue::Low);
+            mock::advance(Duration::from_nanos(300));
+        }
+        mock::set_input("IN", PinValue::High);
+        mock::advance(Duration::from_nanos(499));
+        assert!(edges.borrow().is_empty());
+        mock::advance(Duration::from_nanos(1));
+        assert_eq!(*edges.borrow(), [PinValue::High]);
+    }
+
+    #[test]
+    fn inverted_pin_reads_and_writes_the_logical_level() {
+        let cs = InvertedPin::new(Pin::new("CS", PinMode::OutputHigh));
+        assert!(cs.is_low());
+        assert_eq!(cs.read(), PinValue::Low);
+        cs.set_high();
+        assert_eq!(mock::pin_value("CS"), PinValue::Low);
+        assert!(cs.is_high());
+        cs.toggle();
+        assert_eq!(mock::pin_value("CS"), PinValue::High);
+        cs.write(PinValue::High);
+        assert_eq!(mock::pin_value("CS"), PinValue::Low);
+        assert_eq!(cs.pin().read(), PinValue::Low);
+    }
+
+    #[test]
+    fn inverted_pin_watches_the_inverted_edge() {
+        let irq = InvertedPin::new(Pin::new("IRQ", PinMode::Input));
+        let values = Rc::new(RefCell::new(Vec::new()));
+        let watch_values = values.clone();
+        irq.watch(WatchEdge::Rising, move |pin, value| {
+            assert_eq!(pin.read(), value);
+            watch_values.borrow_mut().push(value)
+        });
+        // The logical level rises when the line falls.
+        mock::set_input("IRQ", PinValue::High);
+        assert!(values.borrow().is_empty());
+        mock::set_input("IRQ", PinValue::Low);
+        mock::set_input("IRQ", PinValue::High);
+        assert_eq!(*values.borrow(), [PinValue::High]);
+        assert!(irq.is_low());
+    }
+
+    #[test]
+    fn inverted_pin_scoped_watch_stops_with_its_guard() {
+        let irq = InvertedPin::new(Pin::new("IRQ", PinMode::Input));
+        let (edges, mut callback) = edge_counter();
+        let guard = irq.watch_scoped(WatchEdge::Falling, move |pin, value| {
+            callback(pin.into_inner(), value)
+        });
+        mock::set_input("IRQ", PinValue::High);
+        assert_eq!(edges.get(), 1);
+        drop(guard);
+        mock::set_input("IRQ", PinValue::Low);
+        mock::set_input("IRQ", PinValue::High);
+        assert_eq!(edges.get(), 1);
+        assert!(!mock::is_watched("IRQ"));
     }
 }
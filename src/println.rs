@@ -1,8 +1,12 @@
 // SPDX-License-Identifier: MIT
 // Author: Uri Shaked
 
+#[cfg(not(feature = "stack-printer"))]
 use std::ffi::CString;
 
+use core::fmt::Write;
+
+#[cfg(not(feature = "stack-printer"))]
 use wokwi_chip_ll::debugPrint;
 
 #[macro_export]
@@ -10,7 +14,7 @@ macro_rules! println {
   ($($arg:tt)*) => {
       {
           use core::fmt::Write;
-          writeln!($crate::println::Printer, $($arg)*).ok();
+          writeln!($crate::println::macro_printer(), $($arg)*).ok();
       }
   };
 }
@@ -20,7 +24,34 @@ macro_rules! print {
   ($($arg:tt)*) => {
       {
           use core::fmt::Write;
-          write!($crate::println::Printer, $($arg)*).ok();
+          write!($crate::println::macro_printer(), $($arg)*).ok();
+      }
+  };
+}
+
+/// Like [`println!`], but for diagnostics: prints to the error channel, see [`STDERR_PREFIX`].
+#[macro_export]
+macro_rules! eprintln {
+  ($($arg:tt)*) => {
+      {
+          use core::fmt::Write;
+          let mut printer = $crate::println::macro_printer();
+          printer.write_str($crate::println::STDERR_PREFIX).ok();
+          writeln!(printer, $($arg)*).ok();
+      }
+  };
+}
+
+/// Like [`print!`], but for diagnostics: prints to the error channel, see [`STDERR_PREFIX`].
+/// Every call is prefixed, so a line printed by several `eprint!` calls has several prefixes.
+#[macro_export]
+macro_rules! eprint {
+  ($($arg:tt)*) => {
+      {
+          use core::fmt::Write;
+          let mut printer = $crate::println::macro_printer();
+          printer.write_str($crate::println::STDERR_PREFIX).ok();
+          write!(printer, $($arg)*).ok();
       }
   };
 }
@@ -29,9 +60,292 @@ pub struct Printer;
 
 impl core::fmt::Write for Printer {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        unsafe {
-            debugPrint(CString::new(s).unwrap().into_raw());
-        }
+        print_str(s);
         core::fmt::Result::Ok(())
     }
 }
+
+/// Prints a string to the debug console as is, without formatting.
+///
+/// NUL bytes can't be passed to the simulator, so they're left out: `"a\0b"` prints `"ab"`.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::println::{print_str, println_str};
+///
+/// print_str("ready: ");
+/// println_str("yes");
+/// ```
+pub fn print_str(s: &str) {
+    for segment in s.split('\0').filter(|segment| !segment.is_empty()) {
+        print_segment(segment);
+    }
+}
+
+/// Prints a string followed by a newline, see [`print_str`].
+pub fn println_str(s: &str) {
+    let mut printer = macro_printer();
+    printer.write_str(s).ok();
+    printer.write_str("\n").ok();
+}
+
+/// Prefix of the lines printed by [`eprintln!`] and [`eprint!`].
+///
+/// The simulator has a single debug console, so diagnostics are printed there too, prefixed with
+/// this to tell them apart from normal output.
+pub const STDERR_PREFIX: &str = "[stderr] ";
+
+/// A printer that collects its output, and prints it a line at a time. Each `write_str` call of
+/// [`Printer`] is a separate call to the simulator, and `write!` makes one for every argument and
+/// every piece of text between them, so buffering saves calls for chatty chips.
+///
+/// A line is printed as soon as it's complete, and the rest is printed by
+/// [`BufferedPrinter::flush`], or when the printer is dropped. [`println!`] and [`print!`] use a
+/// `BufferedPrinter`, unless the `stack-printer` feature is enabled, see
+/// [`stack_printer`](crate::stack_printer).
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use core::fmt::Write;
+/// use wokwi_chips_api::println::BufferedPrinter;
+///
+/// let mut printer = BufferedPrinter::new();
+/// for register in [0x12u8, 0x34, 0x56] {
+///     write!(printer, "{:02x} ", register).ok();
+/// }
+/// printer.flush(); // Prints "12 34 56 " at once.
+/// ```
+#[derive(Default)]
+pub struct BufferedPrinter {
+    buffer: String,
+}
+
+impl BufferedPrinter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prints the buffered output, if any.
+    pub fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            print_str(&self.buffer);
+            self.buffer.clear();
+        }
+    }
+}
+
+impl core::fmt::Write for BufferedPrinter {
+    fn write_str(&mut self, mut s: &str) -> core::fmt::Result {
+        while let Some(index) = s.find('\n') {
+            let (line, rest) = s.split_at(index + 1);
+            self.buffer.push_str(line);
+            self.flush();
+            s = rest;
+        }
+        self.buffer.push_str(s);
+        Ok(())
+    }
+}
+
+impl Drop for BufferedPrinter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// The printer used by the printing macros.
+#[doc(hidden)]
+#[cfg(not(feature = "stack-printer"))]
+pub fn macro_printer() -> BufferedPrinter {
+    BufferedPrinter::new()
+}
+
+/// The printer used by the printing macros. A [`BufferedPrinter`] allocates, so the
+/// `stack-printer` feature uses a [`StackPrinter`](crate::stack_printer::StackPrinter) instead.
+#[doc(hidden)]
+#[cfg(feature = "stack-printer")]
+pub fn macro_printer() -> crate::stack_printer::StackPrinter {
+    crate::stack_printer::StackPrinter::new()
+}
+
+/// Prints a string without NUL bytes.
+#[cfg(not(feature = "stack-printer"))]
+fn print_segment(segment: &str) {
+    // `segment` has no NUL bytes, so this can't fail.
+    let Ok(c_string) = CString::new(segment) else {
+        return;
+    };
+    // The simulator copies the string during the call, so the string is freed afterwards.
+    unsafe {
+        debugPrint(c_string.as_ptr());
+    }
+}
+
+/// Prints a string without NUL bytes, from a stack buffer.
+#[cfg(feature = "stack-printer")]
+fn print_segment(segment: &str) {
+    crate::stack_printer::StackPrinter::new()
+        .write_str(segment)
+        .ok();
+}
+
+/// Prints a line prefixed with a level, e.g. `[WARN] message`. Used by [`info!`](crate::info),
+/// [`warn!`](crate::warn), [`error!`](crate::error) and [`debug!`](crate::debug).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_with_level {
+  ($level:literal, $($arg:tt)*) => {
+      $crate::println!("[{}] {}", $level, format_args!($($arg)*))
+  };
+}
+
+/// Prints a line prefixed with `[INFO]`.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::{error, info, warn};
+///
+/// info!("chip started"); // [INFO] chip started
+/// warn!("address {:#x} busy", 0x50); // [WARN] address 0x50 busy
+/// error!("no clock"); // [ERROR] no clock
+/// ```
+#[macro_export]
+macro_rules! info {
+  ($($arg:tt)*) => {
+      $crate::__log_with_level!("INFO", $($arg)*)
+  };
+}
+
+/// Prints a line prefixed with `[WARN]`.
+#[macro_export]
+macro_rules! warn {
+  ($($arg:tt)*) => {
+      $crate::__log_with_level!("WARN", $($arg)*)
+  };
+}
+
+/// Prints a line prefixed with `[ERROR]`.
+#[macro_export]
+macro_rules! error {
+  ($($arg:tt)*) => {
+      $crate::__log_with_level!("ERROR", $($arg)*)
+  };
+}
+
+/// Prints a line prefixed with `[DEBUG]`, unless the `release-logs-off` feature is enabled.
+#[cfg(not(feature = "release-logs-off"))]
+#[macro_export]
+macro_rules! debug {
+  ($($arg:tt)*) => {
+      $crate::__log_with_level!("DEBUG", $($arg)*)
+  };
+}
+
+/// Prints nothing: the `release-logs-off` feature is enabled. The arguments are still type
+/// checked, so that turning the feature off doesn't break the build, but the code is removed.
+#[cfg(feature = "release-logs-off")]
+#[macro_export]
+macro_rules! debug {
+  ($($arg:tt)*) => {
+      if false {
+          $crate::__log_with_level!("DEBUG", $($arg)*)
+      }
+  };
+}
+
+/// Prints panic messages to the debug console, with the location of the panic. Call it once,
+/// when the chip starts.
+///
+/// Chips are built with the standard library, which prints panic messages to stderr by default,
+/// and stderr isn't shown in the Wokwi console. This replaces the standard panic hook rather than
+/// installing a `#[panic_handler]`, which only `no_std` builds can define, so it doesn't clash
+/// with the standard library: the panic still unwinds or aborts as usual after it's printed.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::println::set_panic_hook;
+///
+/// set_panic_hook();
+/// panic!("no clock");
+/// // Prints "[stderr] panicked at <file>:<line>:<column>:" and "no clock" on the next line.
+/// ```
+pub fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        crate::eprintln!("{}", info);
+    }));
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn print_str_prints_exactly_the_given_text() {
+        print_str("T=25°C {}%s\\n");
+        assert_eq!(mock::take_output(), "T=25°C {}%s\\n");
+        print_str("");
+        assert_eq!(mock::take_output(), "");
+    }
+
+    #[test]
+    fn print_str_leaves_out_nul_bytes() {
+        print_str("a\0b\0\0c\0");
+        assert_eq!(mock::take_output(), "abc");
+    }
+
+    #[test]
+    fn println_str_adds_a_newline() {
+        println_str("ready");
+        println_str("");
+        assert_eq!(mock::take_output(), "ready\n\n");
+    }
+
+    #[test]
+    fn repeated_prints_all_reach_the_console() {
+        // Each print copies its text for the simulator and frees the copy after the call. Run
+        // under Miri to also check that nothing leaks.
+        let mut expected = String::new();
+        for line in 0..1000 {
+            crate::println!("line {}", line);
+            expected.push_str(&format!("line {}\n", line));
+        }
+        assert_eq!(mock::take_output(), expected);
+    }
+
+    #[test]
+    fn long_lines_print_whole() {
+        let line = "é".repeat(300);
+        print_str(&line);
+        assert_eq!(mock::take_output(), line);
+    }
+
+    #[test]
+    fn level_macros_prefix_the_message() {
+        crate::info!("chip started");
+        crate::warn!("address {:#x} busy", 0x50);
+        crate::error!("no clock");
+        assert_eq!(
+            mock::take_output(),
+            "[INFO] chip started\n[WARN] address 0x50 busy\n[ERROR] no clock\n"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "release-logs-off"))]
+    fn debug_prefixes_the_message() {
+        crate::debug!("state {}", 3);
+        assert_eq!(mock::take_output(), "[DEBUG] state 3\n");
+    }
+
+    #[test]
+    #[cfg(feature = "release-logs-off")]
+    fn debug_prints_nothing_with_release_logs_off() {
+        crate::debug!("state {}", 3);
+        assert_eq!(mock::take_output(), "");
+    }
+}
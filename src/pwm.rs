@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::pin::{Pin, PinValue};
+use crate::timer::Timer;
+
+/// Number of timer ticks in each PWM period. This is the duty cycle resolution, i.e. the duty
+/// cycle is rounded to the nearest 1%.
+pub const PWM_STEPS: u32 = 100;
+
+/// The highest PWM frequency, in Hz. Above it, a timer tick would be shorter than the simulator's
+/// 1 ns resolution.
+pub const PWM_MAX_FREQUENCY: f64 = 1e9 / PWM_STEPS as f64;
+
+/// Software PWM output on a pin.
+///
+/// The pin is driven by a timer that fires [`PWM_STEPS`] times per period, so a 1 kHz output
+/// fires the timer every 10 microseconds. Keep the frequency low enough for the simulation to
+/// keep up. The signal stops when the `PwmOutput` is dropped.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::pwm::PwmOutput;
+///
+/// let led = Pin::new("LED", PinMode::Output);
+/// let mut pwm = PwmOutput::new(led, 1000.0, 0.25);
+/// pwm.set_duty(0.75);
+/// ```
+pub struct PwmOutput {
+    pin: Rc<Pin>,
+    timer: Timer,
+    high_steps: Rc<Cell<u32>>,
+}
+
+fn duty_to_steps(duty: f32) -> u32 {
+    (duty.clamp(0.0, 1.0) * PWM_STEPS as f32).round() as u32
+}
+
+impl PwmOutput {
+    /// Starts a PWM signal with the given `frequency` (in Hz) and `duty` cycle (0.0 - 1.0, values
+    /// outside this range are clamped).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frequency` isn't above 0 and at most [`PWM_MAX_FREQUENCY`] (10 MHz).
+    pub fn new(pin: Pin, frequency: f64, duty: f32) -> Self {
+        assert!(
+            frequency > 0.0 && frequency <= PWM_MAX_FREQUENCY,
+            "PWM frequency {} Hz is outside the valid range (0, {}] Hz",
+            frequency,
+            PWM_MAX_FREQUENCY
+        );
+        let pin = Rc::new(pin);
+        let high_steps = Rc::new(Cell::new(duty_to_steps(duty)));
+
+        let timer_pin = pin.clone();
+        let timer_high_steps = high_steps.clone();
+        let step = Cell::new(0);
+        // The duty cycle of the current period. A new duty cycle is only latched at the start of a
+        // period: applied mid-period, a threshold that was already passed would keep the pin high
+        // for the whole period.
+        let period_high_steps = Cell::new(0);
+        let timer = Timer::new(move || {
+            if step.get() == 0 {
+                period_high_steps.set(timer_high_steps.get());
+                timer_pin.write(PinValue::from(period_high_steps.get() > 0));
+            } else if step.get() == period_high_steps.get() {
+                timer_pin.set_low();
+            }
+            step.set((step.get() + 1) % PWM_STEPS);
+        });
+        timer.start_nanos(1e9 / frequency / PWM_STEPS as f64, true);
+
+        Self {
+            pin,
+            timer,
+            high_steps,
+        }
+    }
+
+    /// Sets the duty cycle (0.0 - 1.0, values outside this range are clamped). The new value
+    /// takes effect from the next period.
+    pub fn set_duty(&mut self, duty: f32) {
+        self.high_steps.set(duty_to_steps(duty));
+    }
+
+    /// Returns the duty cycle, rounded to the PWM resolution. After [`PwmOutput::set_duty`], this
+    /// is the new value, even before it takes effect.
+    pub fn duty(&self) -> f32 {
+        self.high_steps.get() as f32 / PWM_STEPS as f32
+    }
+
+    /// Stops the PWM signal and drives the pin low.
+    pub fn stop(&mut self) {
+        self.timer.stop();
+        self.pin.set_low();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock;
+    use crate::pin::PinMode;
+
+    #[test]
+    fn set_duty_takes_effect_from_the_next_period() {
+        // 1 kHz: the timer ticks every 10 µs, and the first period starts at the first tick.
+        let mut pwm = PwmOutput::new(Pin::new("OUT", PinMode::OutputLow), 1000.0, 0.5);
+        mock::advance(Duration::from_micros(315));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+
+        // 30% into the period, lowering the duty to 20% doesn't keep the pin high until the next
+        // period: this period still ends its high phase at 50%.
+        pwm.set_duty(0.2);
+        assert_eq!(pwm.duty(), 0.2);
+        mock::advance(Duration::from_micros(190));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+        mock::advance(Duration::from_micros(10));
+        assert_eq!(mock::pin_value("OUT"), PinValue::Low);
+
+        // The next period, from 1010 µs, runs at 20%.
+        mock::advance(Duration::from_micros(500));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+        mock::advance(Duration::from_micros(190));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+        mock::advance(Duration::from_micros(10));
+        assert_eq!(mock::pin_value("OUT"), PinValue::Low);
+    }
+
+    #[test]
+    fn zero_duty_after_full_duty_drives_the_pin_low() {
+        let mut pwm = PwmOutput::new(Pin::new("OUT", PinMode::OutputLow), 1000.0, 1.0);
+        mock::advance(Duration::from_micros(500));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+        pwm.set_duty(0.0);
+        mock::advance(Duration::from_micros(1000));
+        assert_eq!(mock::pin_value("OUT"), PinValue::Low);
+    }
+
+    #[test]
+    #[should_panic(expected = "PWM frequency 0 Hz is outside the valid range")]
+    fn zero_frequency_panics() {
+        PwmOutput::new(Pin::new("OUT", PinMode::OutputLow), 0.0, 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "PWM frequency 20000000 Hz is outside the valid range")]
+    fn frequency_above_the_maximum_panics() {
+        PwmOutput::new(Pin::new("OUT", PinMode::OutputLow), 2e7, 0.5);
+    }
+
+    #[test]
+    fn maximum_frequency_ticks_every_nanosecond() {
+        let _pwm = PwmOutput::new(Pin::new("OUT", PinMode::OutputLow), PWM_MAX_FREQUENCY, 0.5);
+        mock::advance(Duration::from_nanos(1));
+        assert_eq!(mock::pin_value("OUT"), PinValue::High);
+        mock::advance(Duration::from_nanos(50));
+        assert_eq!(mock::pin_value("OUT"), PinValue::Low);
+    }
+}
@@ -0,0 +1,294 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! Many delayed and periodic tasks running on a single simulator timer.
+//!
+//! The scheduler keeps its tasks sorted by deadline, and starts its timer for the nearest one.
+//! When the timer fires, all the tasks that are due run in deadline order (tasks with the same
+//! deadline run in the order they were scheduled), and the timer is started again for the next
+//! deadline.
+
+use std::cell::{Cell, OnceCell, RefCell};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use crate::time::sim_nanos;
+use crate::timer::{Timer, MIN_PERIOD};
+
+type TaskId = u64;
+
+enum TaskCallback {
+    Once(Box<dyn FnOnce() + 'static>),
+    Every(Box<dyn FnMut() + 'static>),
+}
+
+struct Task {
+    id: TaskId,
+    deadline: u64,
+    period: u64,
+    callback: TaskCallback,
+}
+
+struct SchedulerState {
+    // Sorted by deadline.
+    tasks: RefCell<Vec<Task>>,
+    next_id: Cell<TaskId>,
+    timer: OnceCell<Timer>,
+    // The task whose callback is running, if any, and whether it was cancelled meanwhile.
+    running: Cell<Option<TaskId>>,
+    running_cancelled: Cell<bool>,
+}
+
+impl SchedulerState {
+    fn insert(&self, task: Task) {
+        let mut tasks = self.tasks.borrow_mut();
+        let index = tasks.partition_point(|other| other.deadline <= task.deadline);
+        tasks.insert(index, task);
+    }
+
+    fn add(&self, delay: Duration, period: u64, callback: TaskCallback) -> TaskId {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.insert(Task {
+            id,
+            deadline: sim_nanos() + delay.as_nanos() as u64,
+            period,
+            callback,
+        });
+        self.arm();
+        id
+    }
+
+    fn cancel(&self, id: TaskId) -> bool {
+        let mut tasks = self.tasks.borrow_mut();
+        if let Some(index) = tasks.iter().position(|task| task.id == id) {
+            tasks.remove(index);
+            return true;
+        }
+        if self.running.get() == Some(id) && !self.running_cancelled.get() {
+            self.running_cancelled.set(true);
+            return true;
+        }
+        false
+    }
+
+    // Starts the timer for the nearest deadline, or stops it if there are no tasks.
+    fn arm(&self) {
+        let Some(timer) = self.timer.get() else {
+            return;
+        };
+        match self.tasks.borrow().first() {
+            Some(task) => {
+                timer.start_nanos(task.deadline.saturating_sub(sim_nanos()) as f64, false)
+            }
+            None => timer.stop(),
+        }
+    }
+
+    fn take_due_task(&self) -> Option<Task> {
+        let mut tasks = self.tasks.borrow_mut();
+        if tasks.first()?.deadline > sim_nanos() {
+            return None;
+        }
+        Some(tasks.remove(0))
+    }
+
+    fn run_due_tasks(&self) {
+        while let Some(mut task) = self.take_due_task() {
+            self.running.set(Some(task.id));
+            self.running_cancelled.set(false);
+            // The callback runs without any borrow held, so it can schedule and cancel tasks.
+            let callback = match task.callback {
+                TaskCallback::Once(callback) => {
+                    callback();
+                    None
+                }
+                TaskCallback::Every(mut callback) => {
+                    callback();
+                    Some(callback)
+                }
+            };
+            self.running.set(None);
+            if let Some(callback) = callback {
+                if !self.running_cancelled.get() {
+                    task.deadline += task.period;
+                    task.callback = TaskCallback::Every(callback);
+                    self.insert(task);
+                }
+            }
+        }
+        self.arm();
+    }
+}
+
+/// Runs delayed and periodic tasks, all from a single simulator timer.
+///
+/// Dropping the scheduler cancels all of its tasks.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::scheduler::Scheduler;
+///
+/// let led = Pin::new("LED", PinMode::OutputLow);
+/// let scheduler = Scheduler::new();
+/// let blink = scheduler.schedule_every(Duration::from_millis(500), move || led.toggle());
+/// scheduler.schedule_after(Duration::from_secs(5), move || {
+///     blink.cancel();
+/// });
+/// ```
+pub struct Scheduler {
+    state: Rc<SchedulerState>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        let state = Rc::new(SchedulerState {
+            tasks: RefCell::new(Vec::new()),
+            next_id: Cell::new(0),
+            timer: OnceCell::new(),
+            running: Cell::new(None),
+            running_cancelled: Cell::new(false),
+        });
+        // The timer lives in the state, so it only holds a weak reference back to it.
+        let timer_state = Rc::downgrade(&state);
+        let timer = Timer::new(move || {
+            if let Some(state) = timer_state.upgrade() {
+                state.run_due_tasks();
+            }
+        });
+        let _ = state.timer.set(timer);
+        Self { state }
+    }
+
+    /// Calls `callback` once, `delay` from now.
+    pub fn schedule_after<F>(&self, delay: Duration, callback: F) -> TaskHandle
+    where
+        F: FnOnce() + 'static,
+    {
+        let id = self
+            .state
+            .add(delay, 0, TaskCallback::Once(Box::new(callback)));
+        self.handle(id)
+    }
+
+    /// Calls `callback` every `period`, starting `period` from now, until the task is cancelled.
+    /// Periods shorter than [`MIN_PERIOD`] are rounded up.
+    pub fn schedule_every<F>(&self, period: Duration, callback: F) -> TaskHandle
+    where
+        F: FnMut() + 'static,
+    {
+        let period = period.max(MIN_PERIOD);
+        let id = self.state.add(
+            period,
+            period.as_nanos() as u64,
+            TaskCallback::Every(Box::new(callback)),
+        );
+        self.handle(id)
+    }
+
+    /// Returns the number of tasks waiting to run.
+    pub fn len(&self) -> usize {
+        self.state.tasks.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.tasks.borrow().is_empty()
+    }
+
+    fn handle(&self, id: TaskId) -> TaskHandle {
+        TaskHandle {
+            id,
+            state: Rc::downgrade(&self.state),
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A task scheduled with a [`Scheduler`]. Dropping the handle doesn't cancel the task.
+#[derive(Clone)]
+pub struct TaskHandle {
+    id: TaskId,
+    state: Weak<SchedulerState>,
+}
+
+impl TaskHandle {
+    /// Cancels the task, so its callback isn't called again. A periodic task can cancel itself
+    /// from its callback. Returns false if the task already ran (for a one-shot task), was
+    /// already cancelled, or the scheduler was dropped.
+    pub fn cancel(&self) -> bool {
+        self.state
+            .upgrade()
+            .is_some_and(|state| state.cancel(self.id))
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    type Log = Rc<RefCell<Vec<&'static str>>>;
+
+    // Returns a callback appending `name` to `log`.
+    fn entry(log: &Log, name: &'static str) -> impl FnMut() + 'static {
+        let log = log.clone();
+        move || log.borrow_mut().push(name)
+    }
+
+    #[test]
+    fn tasks_run_in_deadline_order() {
+        let scheduler = Scheduler::new();
+        let log = Log::default();
+        scheduler.schedule_after(Duration::from_micros(30), entry(&log, "c"));
+        scheduler.schedule_after(Duration::from_micros(10), entry(&log, "a"));
+        scheduler.schedule_after(Duration::from_micros(20), entry(&log, "b1"));
+        // Same deadline: scheduling order
+        scheduler.schedule_after(Duration::from_micros(20), entry(&log, "b2"));
+        mock::advance(Duration::from_micros(15));
+        assert_eq!(*log.borrow(), ["a"]);
+        mock::advance(Duration::from_micros(15));
+        assert_eq!(*log.borrow(), ["a", "b1", "b2", "c"]);
+        assert!(scheduler.is_empty());
+        // All on a single timer
+        assert_eq!(mock::timer_count(), 1);
+    }
+
+    #[test]
+    fn periodic_tasks_interleave_and_cancel() {
+        let scheduler = Scheduler::new();
+        let log = Log::default();
+        let fast = scheduler.schedule_every(Duration::from_micros(10), entry(&log, "fast"));
+        scheduler.schedule_every(Duration::from_micros(25), entry(&log, "slow"));
+        mock::advance(Duration::from_micros(50));
+        // At 50 µs, both are due: "slow" was rescheduled for 50 µs first, at 25 µs.
+        assert_eq!(
+            *log.borrow(),
+            ["fast", "fast", "slow", "fast", "fast", "slow", "fast"]
+        );
+
+        assert!(fast.cancel());
+        assert!(!fast.cancel());
+        log.borrow_mut().clear();
+        mock::advance(Duration::from_micros(50));
+        assert_eq!(*log.borrow(), ["slow", "slow"]);
+    }
+
+    #[test]
+    fn dropping_the_scheduler_cancels_its_tasks() {
+        let scheduler = Scheduler::new();
+        let log = Log::default();
+        let task = scheduler.schedule_after(Duration::from_micros(10), entry(&log, "a"));
+        drop(scheduler);
+        mock::advance(Duration::from_micros(20));
+        assert!(log.borrow().is_empty());
+        assert!(!task.cancel());
+    }
+}
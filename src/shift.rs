@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! Bit-banged serial helpers, similar to Arduino's `shiftOut()` and `shiftIn()`.
+//!
+//! The pins are toggled from the chip code without any delay between the edges: simulation time
+//! doesn't advance while the chip code runs, and parts connected to the pins see the edges in the
+//! order they were written. This is enough for edge-triggered parts such as the 74HC595.
+
+use crate::bus::BitOrder;
+use crate::pin::{Pin, PinValue};
+
+fn bit_mask(bit: u8, bit_order: BitOrder) -> u8 {
+    match bit_order {
+        BitOrder::LsbFirst => 1 << bit,
+        BitOrder::MsbFirst => 0x80 >> bit,
+    }
+}
+
+/// Shifts out `value` on `data_pin`, one bit at a time, pulsing `clock_pin` high after setting
+/// each bit.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::bus::BitOrder;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::shift::shift_out;
+///
+/// let data = Pin::new("DS", PinMode::OutputLow);
+/// let clock = Pin::new("SHCP", PinMode::OutputLow);
+/// let latch = Pin::new("STCP", PinMode::OutputLow);
+/// shift_out(&data, &clock, 0b1010_0101, BitOrder::MsbFirst);
+/// latch.set_high();
+/// latch.set_low();
+/// ```
+///
+pub fn shift_out(data_pin: &Pin, clock_pin: &Pin, value: u8, bit_order: BitOrder) {
+    for bit in 0..8 {
+        data_pin.write(PinValue::from(value & bit_mask(bit, bit_order) != 0));
+        clock_pin.set_high();
+        clock_pin.set_low();
+    }
+}
+
+/// Shifts in a byte from `data_pin`, one bit at a time. Each bit is read while `clock_pin` is
+/// high.
+pub fn shift_in(data_pin: &Pin, clock_pin: &Pin, bit_order: BitOrder) -> u8 {
+    let mut value = 0;
+    for bit in 0..8 {
+        clock_pin.set_high();
+        if data_pin.is_high() {
+            value |= bit_mask(bit, bit_order);
+        }
+        clock_pin.set_low();
+    }
+    value
+}
@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::mem;
+
+use wokwi_chip_ll::{spiInit, spiStart, spiStop, SPIConfig, SPIDevId};
+
+use crate::pin::Pin;
+
+/// SPI clock polarity and phase, as in the usual SPI mode numbers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum SpiMode {
+    /// Clock idles low, data is sampled on the rising edge.
+    #[default]
+    Mode0 = 0,
+    /// Clock idles low, data is sampled on the falling edge.
+    Mode1 = 1,
+    /// Clock idles high, data is sampled on the falling edge.
+    Mode2 = 2,
+    /// Clock idles high, data is sampled on the rising edge.
+    Mode3 = 3,
+}
+
+pub struct SpiDeviceConfig {
+    pub sck: Pin,
+    pub mosi: Pin,
+    pub miso: Pin,
+    pub mode: SpiMode,
+}
+
+type SpiCallback = Box<dyn FnMut(&[u8]) + 'static>;
+
+// SPI callbacks only receive the `user_data` pointer, so each device gets a registry key that is
+// passed as `user_data` and used to look up its callback.
+type SpiKey = usize;
+
+struct SpiListener {
+    key: SpiKey,
+    id: SPIDevId,
+    // Taken out while the callback runs, so that the callback is free to access the registry.
+    callback: Option<SpiCallback>,
+    // The buffer of the transfer in progress. The simulator reads and writes it until it calls
+    // `done`, so it isn't touched until then.
+    buffer: Vec<u8>,
+}
+
+thread_local! {
+    // This is a global registry of all the SPI devices, so that we can keep the Rust callbacks
+    // and transfer buffers alive as long as the device exists.
+    static SPI_REGISTRY: RefCell<Vec<SpiListener>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SPI_KEY: Cell<SpiKey> = const { Cell::new(0) };
+}
+
+fn register_device(callback: SpiCallback) -> SpiKey {
+    let key = NEXT_SPI_KEY.get();
+    NEXT_SPI_KEY.set(key + 1);
+    SPI_REGISTRY.with_borrow_mut(|registry| {
+        registry.push(SpiListener {
+            key,
+            id: 0,
+            callback: Some(callback),
+            buffer: Vec::new(),
+        });
+    });
+    key
+}
+
+fn unregister_device(key: SpiKey) {
+    SPI_REGISTRY.with_borrow_mut(|registry| {
+        if let Some(index) = registry.iter().position(|listener| listener.key == key) {
+            let listener = registry.remove(index);
+            // The simulator may still be using the buffer of the transfer in progress.
+            mem::forget(listener.buffer);
+        }
+    });
+}
+
+fn with_listener<R>(key: SpiKey, f: impl FnOnce(&mut SpiListener) -> R) -> Option<R> {
+    SPI_REGISTRY.with_borrow_mut(|registry| {
+        registry
+            .iter_mut()
+            .find(|listener| listener.key == key)
+            .map(f)
+    })
+}
+
+extern "C" fn spi_done_trampoline(user_data: *mut c_void, _buffer: *mut u8, count: u32) {
+    let key = user_data as SpiKey;
+    // The transfer is over, so the buffer and the callback can be taken out of the registry. The
+    // callback can then start the next transfer.
+    let Some((buffer, callback)) = with_listener(key, |listener| {
+        (mem::take(&mut listener.buffer), listener.callback.take())
+    }) else {
+        return;
+    };
+    let Some(mut callback) = callback else {
+        return;
+    };
+    let count = (count as usize).min(buffer.len());
+    callback(&buffer[..count]);
+    with_listener(key, |listener| {
+        // If the callback replaced itself, the old callback is dropped here.
+        if listener.callback.is_none() {
+            listener.callback = Some(callback);
+        }
+    });
+}
+
+/// An SPI peripheral, e.g. a flash chip or an ADC.
+///
+/// Dropping the device stops the transfer in progress and releases its callback and pins. The
+/// simulator can't remove an SPI device, so after that it no longer takes part in transfers, and
+/// a new `SpiDevice` can be created on the same pins, e.g. when an attribute changes the SPI
+/// mode.
+///
+/// Example, a device that logs every byte it receives while its CS pin is low:
+///
+/// ```rust,no_run
+/// use std::rc::Rc;
+/// use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+/// use wokwi_chips_api::spi::{SpiDevice, SpiDeviceConfig, SpiMode};
+///
+/// let cs = Pin::new("CS", PinMode::Input);
+/// let spi = Rc::new(SpiDevice::new(
+///     SpiDeviceConfig {
+///         sck: Pin::new("SCK", PinMode::Input),
+///         mosi: Pin::new("MOSI", PinMode::Input),
+///         miso: Pin::new("MISO", PinMode::Input),
+///         mode: SpiMode::Mode0,
+///     },
+///     |received| println!("SPI received: {:02x?}", received),
+/// ));
+/// let cs_spi = spi.clone();
+/// cs.watch(WatchEdge::Both, move |_, value| {
+///     if value == PinValue::Low {
+///         cs_spi.start_receive(1);
+///     } else {
+///         cs_spi.stop();
+///     }
+/// });
+/// ```
+pub struct SpiDevice {
+    id: SPIDevId,
+    key: SpiKey,
+    sck: Pin,
+    mosi: Pin,
+    miso: Pin,
+}
+
+impl SpiDevice {
+    /// Creates an SPI device. The device only shifts data while a transfer is in progress, so
+    /// chips usually start a transfer when their CS pin goes low, and stop it when CS goes high.
+    /// `done_callback` gets the bytes received in each transfer, see [`SpiDevice::on_done`].
+    pub fn new<F>(config: SpiDeviceConfig, done_callback: F) -> Self
+    where
+        F: FnMut(&[u8]) + 'static,
+    {
+        let SpiDeviceConfig {
+            sck,
+            mosi,
+            miso,
+            mode,
+        } = config;
+        let key = register_device(Box::new(done_callback));
+        let ll_config = SPIConfig {
+            user_data: key as *const c_void,
+            sck: sck.get_id(),
+            mosi: mosi.get_id(),
+            miso: miso.get_id(),
+            mode: mode as u32,
+            done: spi_done_trampoline as *const c_void,
+        };
+        let id = unsafe { spiInit(&ll_config) };
+        with_listener(key, |listener| listener.id = id);
+
+        Self {
+            id,
+            key,
+            sck,
+            mosi,
+            miso,
+        }
+    }
+
+    /// Replaces the done callback, which is called whenever a transfer completes or is stopped,
+    /// with the bytes the controller sent during the transfer.
+    ///
+    /// The received slice is only valid during the callback: the transfer buffer is released
+    /// when the callback returns, so copy whatever you need to keep. The callback may start the
+    /// next transfer, e.g. to keep receiving while CS is low.
+    pub fn on_done<F>(&self, callback: F)
+    where
+        F: FnMut(&[u8]) + 'static,
+    {
+        with_listener(self.key, |listener| {
+            listener.callback = Some(Box::new(callback));
+        });
+    }
+
+    fn start_with_buffer(&self, buffer: Vec<u8>) {
+        let transfer = with_listener(self.key, |listener| {
+            // A transfer that is still in progress may still be using its buffer.
+            mem::forget(mem::replace(&mut listener.buffer, buffer));
+            (listener.buffer.as_ptr(), listener.buffer.len() as u32)
+        });
+        if let Some((buffer, count)) = transfer {
+            unsafe {
+                spiStart(self.id, buffer, count);
+            }
+        }
+    }
+
+    /// Starts a full-duplex transfer: the bytes of `tx` are shifted out on MISO, while the same
+    /// number of bytes are received on MOSI. The done callback gets the received bytes once
+    /// `tx.len()` bytes were transferred, or the transfer is stopped.
+    ///
+    /// The simulator keeps using the transfer buffer after this returns, until the transfer is
+    /// done. That's why `tx` is copied to a buffer owned by the device, and the received bytes are
+    /// passed to the done callback rather than written to a caller's buffer: there's no borrowed
+    /// memory for the simulator to outlive. Starting a transfer while another is in progress
+    /// replaces it; the simulator may still reference the old buffer, so it's leaked rather than
+    /// freed.
+    ///
+    /// Example, a device that answers a command byte with a two-byte reading:
+    ///
+    /// ```rust,no_run
+    /// use std::rc::Rc;
+    /// use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+    /// use wokwi_chips_api::spi::{SpiDevice, SpiDeviceConfig, SpiMode};
+    ///
+    /// let cs = Pin::new("CS", PinMode::Input);
+    /// let spi = Rc::new(SpiDevice::new(
+    ///     SpiDeviceConfig {
+    ///         sck: Pin::new("SCK", PinMode::Input),
+    ///         mosi: Pin::new("MOSI", PinMode::Input),
+    ///         miso: Pin::new("MISO", PinMode::Input),
+    ///         mode: SpiMode::Mode0,
+    ///     },
+    ///     |received| println!("command: {:02x?}", received.first()),
+    /// ));
+    /// let cs_spi = spi.clone();
+    /// cs.watch(WatchEdge::Both, move |_, value| {
+    ///     if value == PinValue::Low {
+    ///         // The first byte is clocked out while the command arrives.
+    ///         cs_spi.start_transfer(&[0x00, 0x12, 0x34]);
+    ///     } else {
+    ///         cs_spi.stop();
+    ///     }
+    /// });
+    /// ```
+    pub fn start_transfer(&self, tx: &[u8]) {
+        self.start_with_buffer(tx.to_vec());
+    }
+
+    /// Starts a transfer of `count` bytes, sending zeros on MISO. See
+    /// [`SpiDevice::start_transfer`].
+    pub fn start_receive(&self, count: usize) {
+        self.start_with_buffer(vec![0; count]);
+    }
+
+    /// Stops the transfer in progress. The done callback is called with the bytes received so
+    /// far. Does nothing if no transfer is in progress, so it's safe to call more than once.
+    pub fn stop(&self) {
+        // The done trampoline takes the buffer, so a non-empty buffer means a transfer is running.
+        let in_progress = with_listener(self.key, |listener| !listener.buffer.is_empty());
+        if in_progress == Some(true) {
+            unsafe {
+                spiStop(self.id);
+            }
+        }
+    }
+
+    pub fn sck(&self) -> &Pin {
+        &self.sck
+    }
+
+    pub fn mosi(&self) -> &Pin {
+        &self.mosi
+    }
+
+    pub fn miso(&self) -> &Pin {
+        &self.miso
+    }
+
+    pub fn get_id(&self) -> SPIDevId {
+        self.id
+    }
+}
+
+impl Drop for SpiDevice {
+    fn drop(&mut self) {
+        self.stop();
+        unregister_device(self.key);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::mock;
+    use crate::pin::PinMode;
+
+    fn config() -> SpiDeviceConfig {
+        SpiDeviceConfig {
+            sck: Pin::new("SCK", PinMode::Input),
+            mosi: Pin::new("MOSI", PinMode::Input),
+            miso: Pin::new("MISO", PinMode::Input),
+            mode: SpiMode::Mode0,
+        }
+    }
+
+    // A device that records the bytes received in each transfer.
+    fn recording_device() -> (SpiDevice, Rc<RefCell<Vec<Vec<u8>>>>) {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let done_received = received.clone();
+        let spi = SpiDevice::new(config(), move |bytes| {
+            done_received.borrow_mut().push(bytes.to_vec())
+        });
+        (spi, received)
+    }
+
+    #[test]
+    fn transfers_are_full_duplex() {
+        let (spi, received) = recording_device();
+        spi.start_transfer(&[0xa1, 0xa2, 0xa3]);
+        assert_eq!(
+            mock::spi_transfer(spi.get_id(), &[0x01, 0x02]),
+            [0xa1, 0xa2]
+        );
+        assert!(received.borrow().is_empty());
+        assert_eq!(mock::spi_transfer(spi.get_id(), &[0x03]), [0xa3]);
+        assert_eq!(*received.borrow(), [vec![0x01, 0x02, 0x03]]);
+
+        // No transfer is in progress.
+        assert!(mock::spi_transfer(spi.get_id(), &[0x04]).is_empty());
+    }
+
+    #[test]
+    fn stopping_a_transfer_delivers_the_bytes_received_so_far() {
+        let (spi, received) = recording_device();
+        spi.start_transfer(&[0xa1, 0xa2, 0xa3]);
+        mock::spi_transfer(spi.get_id(), &[0x01]);
+        spi.stop();
+        assert_eq!(*received.borrow(), [vec![0x01]]);
+    }
+
+    #[test]
+    fn on_done_replaces_the_callback() {
+        let (spi, first) = recording_device();
+        let second = Rc::new(RefCell::new(Vec::new()));
+        let done_second = second.clone();
+        spi.on_done(move |bytes| done_second.borrow_mut().push(bytes.to_vec()));
+        spi.start_receive(2);
+        mock::spi_transfer(spi.get_id(), &[0x5a, 0xa5]);
+        assert!(first.borrow().is_empty());
+        assert_eq!(*second.borrow(), [vec![0x5a, 0xa5]]);
+    }
+
+    #[test]
+    fn the_done_callback_can_start_the_next_transfer() {
+        let (spi, received) = recording_device();
+        let spi = Rc::new(spi);
+        let done_spi = Rc::downgrade(&spi);
+        let done_received = received.clone();
+        spi.on_done(move |bytes| {
+            done_received.borrow_mut().push(bytes.to_vec());
+            if let Some(spi) = done_spi.upgrade() {
+                spi.start_transfer(&[bytes[0] + 1]);
+            }
+        });
+        spi.start_transfer(&[0x10]);
+        assert_eq!(
+            mock::spi_transfer(spi.get_id(), &[0x01, 0x02, 0x03]),
+            [0x10, 0x02, 0x03]
+        );
+        assert_eq!(*received.borrow(), [vec![0x01], vec![0x02], vec![0x03]]);
+    }
+
+    #[test]
+    fn dropped_devices_leave_the_registry() {
+        let registered = || SPI_REGISTRY.with_borrow(Vec::len);
+        let before = registered();
+        let (spi, _) = recording_device();
+        spi.start_receive(4);
+        drop(spi);
+        assert_eq!(registered(), before);
+
+        // A device created on the same pins takes the dropped device's place.
+        let (spi, _) = recording_device();
+        assert_eq!(registered(), before + 1);
+        drop(spi);
+        assert_eq!(registered(), before);
+    }
+
+    #[test]
+    fn dropping_a_stopped_device_doesnt_stop_it_again() {
+        let (spi, received) = recording_device();
+        let id = spi.get_id();
+        spi.start_receive(4);
+        spi.stop();
+        spi.stop();
+        assert_eq!(mock::spi_stop_count(id), 1);
+        drop(spi);
+        assert_eq!(mock::spi_stop_count(id), 1);
+        assert_eq!(*received.borrow(), [Vec::<u8>::new()]);
+    }
+
+    #[test]
+    fn dropping_a_device_stops_its_transfer() {
+        let (spi, received) = recording_device();
+        let id = spi.get_id();
+        spi.start_receive(4);
+        mock::spi_transfer(id, &[0x42]);
+        drop(spi);
+        assert_eq!(mock::spi_stop_count(id), 1);
+        assert_eq!(*received.borrow(), [vec![0x42]]);
+    }
+}
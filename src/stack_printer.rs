@@ -0,0 +1,135 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! Printing without the standard library or an allocator, enabled by the `stack-printer` feature.
+//!
+//! The default printing path builds a `CString` for every write, which needs `std` (and an
+//! allocator). [`StackPrinter`] only uses `core`: it collects the output in a buffer on the stack,
+//! and passes it to the simulator a line at a time. Lines that don't fit in the buffer are printed
+//! in chunks. With the feature enabled, [`println!`](crate::println!) and the other printing macros
+//! use it, so printing doesn't depend on `std`. The rest of the crate still does (e.g. `Rc`, `Vec`
+//! and the `thread_local!` callback registries).
+
+use core::ffi::c_char;
+use core::fmt;
+
+use wokwi_chip_ll::debugPrint;
+
+/// Size of the [`StackPrinter`] buffer, including the NUL terminator.
+pub const STACK_BUFFER_SIZE: usize = 128;
+
+/// A printer that collects its output in a stack buffer, and prints it a line at a time, like
+/// [`BufferedPrinter`](crate::println::BufferedPrinter) does without the feature.
+///
+/// A line is printed as soon as it's complete, or once it fills the buffer, and the rest is
+/// printed by [`StackPrinter::flush`], or when the printer is dropped. Chunks end on character
+/// boundaries, so multibyte characters aren't split. NUL bytes can't be passed to the simulator,
+/// so they're left out.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use core::fmt::Write;
+/// use wokwi_chips_api::stack_printer::StackPrinter;
+///
+/// let mut printer = StackPrinter::new();
+/// write!(printer, "T={}°C", 25).ok();
+/// printer.flush(); // Prints "T=25°C" at once.
+/// ```
+pub struct StackPrinter {
+    buffer: [u8; STACK_BUFFER_SIZE],
+    len: usize,
+}
+
+impl StackPrinter {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; STACK_BUFFER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Prints the buffered output, if any.
+    pub fn flush(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        self.buffer[self.len] = 0;
+        // The simulator copies the string during the call, so the buffer can be reused.
+        unsafe {
+            debugPrint(self.buffer.as_ptr() as *const c_char);
+        }
+        self.len = 0;
+    }
+
+    // Buffers `s`, which has no NUL bytes, flushing whenever the buffer is full.
+    fn push(&mut self, mut s: &str) {
+        while !s.is_empty() {
+            let mut len = s.len().min(STACK_BUFFER_SIZE - 1 - self.len);
+            while !s.is_char_boundary(len) {
+                len -= 1;
+            }
+            if len == 0 {
+                // The next character doesn't fit anymore.
+                self.flush();
+                continue;
+            }
+            self.buffer[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+            self.len += len;
+            s = &s[len..];
+        }
+    }
+}
+
+impl Default for StackPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for StackPrinter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            for segment in line.split('\0') {
+                self.push(segment);
+            }
+            if line.ends_with('\n') {
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for StackPrinter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn lines_are_printed_as_soon_as_they_are_complete() {
+        let mut printer = StackPrinter::new();
+        write!(printer, "a={} ", 1).ok();
+        write!(printer, "b={}\nc", 2).ok();
+        assert_eq!(mock::take_output(), "a=1 b=2\n");
+        drop(printer);
+        assert_eq!(mock::take_output(), "c");
+    }
+
+    #[test]
+    fn long_lines_are_printed_in_chunks() {
+        let line: String = "é".repeat(100) + "\n";
+        let mut printer = StackPrinter::new();
+        printer.write_str("x\0").ok();
+        printer.write_str(&line).ok();
+        assert_eq!(mock::take_output(), String::from("x") + &line);
+    }
+}
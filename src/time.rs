@@ -0,0 +1,227 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! Simulation time.
+//!
+//! The simulation clock starts at zero when the simulation starts, and only advances while the
+//! simulation runs, independently of wall-clock time.
+//!
+//! The clock doesn't advance while chip code runs: the simulation waits for each callback to
+//! return. So a chip can't wait by spinning until the clock reaches a time, it would spin
+//! forever. Await a [`delay`] in an async task, or use [`Timer::once`](crate::timer::Timer::once)
+//! or a [`Scheduler`](crate::scheduler::Scheduler) to continue after a delay instead.
+
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
+use wokwi_chip_ll::getSimNanos;
+
+use crate::timer::{sleep, Sleep};
+
+/// Returns the current simulation time, in nanoseconds since the simulation started.
+pub fn sim_nanos() -> u64 {
+    // The simulator counts in whole nanoseconds, stored in a double.
+    unsafe { getSimNanos() as u64 }
+}
+
+/// Returns the current simulation time, since the simulation started.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::println;
+/// use wokwi_chips_api::time::sim_time;
+///
+/// println!("Chip started at {:?}", sim_time());
+/// ```
+pub fn sim_time() -> Duration {
+    Duration::from_nanos(sim_nanos())
+}
+
+/// A point in simulation time, like [`std::time::Instant`] for the simulation clock.
+///
+/// Example, measuring the time between two edges:
+///
+/// ```rust,no_run
+/// use std::cell::Cell;
+/// use wokwi_chips_api::pin::{Pin, PinMode, WatchEdge};
+/// use wokwi_chips_api::time::Instant;
+///
+/// let input = Pin::new("IN", PinMode::Input);
+/// let last_edge = Cell::new(Instant::now());
+/// input.watch(WatchEdge::Rising, move |_, _| {
+///     println!("Period: {:?}", last_edge.replace(Instant::now()).elapsed());
+/// });
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    nanos: u64,
+}
+
+impl Instant {
+    /// Returns the current simulation time.
+    pub fn now() -> Self {
+        Self::from_sim_nanos(sim_nanos())
+    }
+
+    /// Returns the instant `nanos` nanoseconds after the simulation started.
+    pub const fn from_sim_nanos(nanos: u64) -> Self {
+        Self { nanos }
+    }
+
+    /// Returns the time since the simulation started, in nanoseconds.
+    pub const fn as_sim_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns the time passed since this instant.
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Returns the time from `earlier` to this instant, or zero if `earlier` is later.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::time::Instant;
+    ///
+    /// let start = Instant::from_sim_nanos(1_000);
+    /// let end = Instant::from_sim_nanos(4_000);
+    /// assert!(start < end);
+    /// assert_eq!(end.duration_since(start), Duration::from_micros(3));
+    /// assert_eq!(start.duration_since(end), Duration::ZERO);
+    /// assert_eq!(start.checked_duration_since(end), None);
+    /// ```
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    /// Returns the time from `earlier` to this instant, or `None` if `earlier` is later.
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.nanos
+            .checked_sub(earlier.nanos)
+            .map(Duration::from_nanos)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant::from_sim_nanos(self.nanos + duration.as_nanos() as u64)
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant::from_sim_nanos(self.nanos.saturating_sub(duration.as_nanos() as u64))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    /// Same as [`Instant::duration_since`].
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Waits for `duration` of simulation time, without blocking the chip: the returned future
+/// completes when the time has passed, and other callbacks run in the meantime. This is
+/// [`sleep`] under the name used for bit-banging, so it only works in a task of the
+/// [`executor`](crate::executor).
+///
+/// Example, a 10 µs reset pulse:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::pin::Pin;
+/// use wokwi_chips_api::time::delay;
+///
+/// async fn reset(rst: &Pin) {
+///     rst.set_low();
+///     delay(Duration::from_micros(10)).await;
+///     rst.set_high();
+/// }
+/// ```
+pub fn delay(duration: Duration) -> Sleep {
+    sleep(duration)
+}
+
+/// Like [`delay`], for `micros` microseconds.
+pub fn delay_micros(micros: u64) -> Sleep {
+    delay(Duration::from_micros(micros))
+}
+
+/// Like [`delay`], for `nanos` nanoseconds.
+pub fn delay_nanos(nanos: u64) -> Sleep {
+    delay(Duration::from_nanos(nanos))
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::executor;
+    use crate::mock;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn delays_wait_for_at_least_the_requested_time() {
+        let steps = Rc::new(RefCell::new(Vec::new()));
+        let task_steps = steps.clone();
+        executor::spawn(async move {
+            let start = Instant::now();
+            delay(Duration::from_micros(10)).await;
+            task_steps.borrow_mut().push(start.elapsed());
+            delay_micros(5).await;
+            task_steps.borrow_mut().push(start.elapsed());
+            delay_nanos(500).await;
+            task_steps.borrow_mut().push(start.elapsed());
+        });
+        executor::run();
+
+        mock::advance(Duration::from_micros(9));
+        assert!(steps.borrow().is_empty());
+        mock::advance(Duration::from_micros(20));
+        assert_eq!(
+            *steps.borrow(),
+            [
+                Duration::from_micros(10),
+                Duration::from_micros(15),
+                Duration::from_nanos(15_500)
+            ]
+        );
+    }
+
+    #[test]
+    fn instants_follow_the_simulation_clock() {
+        let start = Instant::now();
+        assert_eq!(start.as_sim_nanos(), 0);
+        mock::advance(Duration::from_micros(250));
+        let end = Instant::now();
+        assert!(start < end);
+        assert_eq!(end - start, Duration::from_micros(250));
+        assert_eq!(start.elapsed(), Duration::from_micros(250));
+        assert_eq!(end.elapsed(), Duration::ZERO);
+        assert_eq!(sim_time(), Duration::from_micros(250));
+    }
+
+    #[test]
+    fn durations_between_instants_never_underflow() {
+        let earlier = Instant::from_sim_nanos(1_000);
+        let later = earlier + Duration::from_nanos(500);
+        assert_eq!(later.duration_since(earlier), Duration::from_nanos(500));
+        assert_eq!(earlier.duration_since(later), Duration::ZERO);
+        assert_eq!(earlier - later, Duration::ZERO);
+        assert_eq!(earlier.checked_duration_since(later), None);
+        assert_eq!(earlier - Duration::from_secs(1), Instant::from_sim_nanos(0));
+        // An instant after the current time hasn't elapsed yet.
+        assert_eq!(later.elapsed(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,647 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+//! Simulator timers calling Rust closures.
+//!
+//! Timers run on simulation time: a timer started with a 1 ms period fires after 1 ms of
+//! simulated time, regardless of how fast the simulation runs.
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use wokwi_chip_ll::{timerInit, timerStart, timerStartNanos, timerStop, TimerConfig, TimerId};
+
+use crate::time::sim_time;
+
+type TimerCallback = Box<dyn FnMut() + 'static>;
+
+// Timer callbacks only receive the `user_data` pointer, so each timer gets a registry key that is
+// passed as `user_data` and used to look up its callback.
+type TimerKey = usize;
+
+struct TimerListener {
+    key: TimerKey,
+    // Taken out while the callback runs, so that the callback is free to access the registry.
+    callback: Option<TimerCallback>,
+}
+
+thread_local! {
+    // This is a global registry of all the timers, so that we can keep the Rust callbacks alive as
+    // long as the timer exists.
+    static TIMER_REGISTRY: RefCell<Vec<TimerListener>> = const { RefCell::new(Vec::new()) };
+    static NEXT_TIMER_KEY: Cell<TimerKey> = const { Cell::new(0) };
+    // The simulator can't free a timer, so the timers of dropped `Timer`s and finished one-shot
+    // calls are kept here, stopped, along with the key they pass as `user_data`, and reused by
+    // the next ones.
+    static FREE_TIMERS: RefCell<Vec<(TimerId, TimerKey)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Creates a simulator timer, and returns it with the registry key it calls back with.
+fn new_sim_timer() -> (TimerId, TimerKey) {
+    let key = NEXT_TIMER_KEY.get();
+    NEXT_TIMER_KEY.set(key + 1);
+    let config = TimerConfig {
+        user_data: key as *const c_void,
+        callback: timer_trampoline as *const c_void,
+    };
+    let id = unsafe { timerInit(&config) };
+    (id, key)
+}
+
+/// Returns a stopped simulator timer, reusing a free one if there is any.
+fn acquire_sim_timer() -> (TimerId, TimerKey) {
+    FREE_TIMERS
+        .with_borrow_mut(|free| free.pop())
+        .unwrap_or_else(new_sim_timer)
+}
+
+/// Returns a stopped simulator timer, whose key has no callback registered, to the free list.
+fn release_sim_timer(id: TimerId, key: TimerKey) {
+    // A timer dropped while the thread exits may outlive the pool, and isn't reused anyway.
+    let _ = FREE_TIMERS.try_with(|free| free.borrow_mut().push((id, key)));
+}
+
+fn register_timer(key: TimerKey, callback: TimerCallback) {
+    TIMER_REGISTRY.with_borrow_mut(|registry| {
+        registry.push(TimerListener {
+            key,
+            callback: Some(callback),
+        });
+    });
+}
+
+fn unregister_timer(key: TimerKey) {
+    TIMER_REGISTRY.with_borrow_mut(|registry| registry.retain(|listener| listener.key != key));
+}
+
+fn take_callback(key: TimerKey) -> Option<TimerCallback> {
+    TIMER_REGISTRY.with_borrow_mut(|registry| {
+        registry
+            .iter_mut()
+            .find(|listener| listener.key == key)
+            .and_then(|listener| listener.callback.take())
+    })
+}
+
+fn restore_callback(key: TimerKey, callback: TimerCallback) {
+    TIMER_REGISTRY.with_borrow_mut(|registry| {
+        // If the timer was dropped while the callback was running, the callback is dropped here.
+        // So it is if the key was reused by a new timer in the meantime: that timer's callback
+        // is already in place.
+        if let Some(listener) = registry.iter_mut().find(|listener| listener.key == key) {
+            if listener.callback.is_none() {
+                listener.callback = Some(callback);
+            }
+        }
+    });
+}
+
+extern "C" fn timer_trampoline(user_data: *mut c_void) {
+    let key = user_data as TimerKey;
+    let Some(mut callback) = take_callback(key) else {
+        return;
+    };
+    callback();
+    restore_callback(key, callback);
+}
+
+/// The shortest period of a repeating timer. Shorter periods, including zero, are rounded up to
+/// this, since a zero period would fire the timer forever without advancing the simulation time.
+pub const MIN_PERIOD: Duration = Duration::from_nanos(1);
+
+/// A simulator timer calling a Rust closure.
+///
+/// Dropping the timer stops it and releases the closure, so a dropped timer never calls back
+/// into freed state. The simulator can't free a timer, so the next timer created reuses it. If
+/// the timer should run for the whole lifetime of the chip, and there's no chip state to keep it
+/// in, pass it to [`std::mem::forget`]: this deliberately leaks the timer and its closure, and
+/// the timer keeps firing.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::timer::Timer;
+///
+/// let pin = Pin::new("OUT", PinMode::OutputLow);
+/// let timer = Timer::new(move || pin.toggle());
+/// timer.start(Duration::from_millis(500), true);
+/// // Keep `timer` alive (e.g. in the chip state) for as long as it should run.
+/// ```
+pub struct Timer {
+    id: TimerId,
+    key: TimerKey,
+    state: Rc<TimerState>,
+}
+
+// Shared with the callback, so that a one-shot timer knows it's no longer running after it fired.
+#[derive(Default)]
+struct TimerState {
+    period_nanos: Cell<f64>,
+    repeat: Cell<bool>,
+    running: Cell<bool>,
+    paused: Cell<bool>,
+}
+
+impl Timer {
+    /// Creates a stopped timer that calls `callback` every time it fires. The callback is kept
+    /// alive by the timer, and released when the timer is dropped.
+    ///
+    /// Example, counting ticks:
+    ///
+    /// ```rust,no_run
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// let ticks = Rc::new(Cell::new(0));
+    /// let timer_ticks = ticks.clone();
+    /// let timer = Timer::new(move || timer_ticks.set(timer_ticks.get() + 1));
+    /// timer.start(Duration::from_millis(1), true);
+    /// // After 10 ms of simulation time, `ticks.get()` returns 10.
+    /// ```
+    pub fn new<F>(mut callback: F) -> Self
+    where
+        F: FnMut() + 'static,
+    {
+        let (id, key) = acquire_sim_timer();
+        let state = Rc::new(TimerState::default());
+        let callback_state = state.clone();
+        register_timer(
+            key,
+            Box::new(move || {
+                if !callback_state.repeat.get() {
+                    callback_state.running.set(false);
+                }
+                callback();
+            }),
+        );
+
+        Self { id, key, state }
+    }
+
+    fn set_started(&self, nanos: f64, repeat: bool) {
+        self.state.period_nanos.set(nanos);
+        self.state.repeat.set(repeat);
+        self.state.running.set(true);
+        self.state.paused.set(false);
+    }
+
+    /// Creates a timer that calls `callback` every `period`, starting `period` from now, until
+    /// it's stopped or dropped.
+    ///
+    /// The simulator schedules timers with nanosecond resolution, the same as [`Duration`], so
+    /// the period isn't rounded, except that periods shorter than [`MIN_PERIOD`] are rounded up.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// let pin = Pin::new("CLK", PinMode::OutputLow);
+    /// let clock = Timer::periodic(Duration::from_micros(50), move || pin.toggle());
+    /// // Double the clock frequency, from 10 kHz to 20 kHz.
+    /// clock.set_period(Duration::from_micros(25));
+    /// ```
+    pub fn periodic<F>(period: Duration, callback: F) -> Self
+    where
+        F: FnMut() + 'static,
+    {
+        let timer = Self::new(callback);
+        timer.start(period, true);
+        timer
+    }
+
+    /// Calls `callback` once, `delay` from now. There's no handle to keep alive: the timer
+    /// releases the callback after it fires.
+    ///
+    /// The callback is taken out of the timer registry while it runs, so the timer unregisters
+    /// itself from inside the callback, and the callback is only dropped after it returns. The
+    /// simulator timer is then reused by later calls, so calling this repeatedly (e.g. from
+    /// [`Pin::pulse`](crate::pin::Pin::pulse)) only creates as many simulator timers as there
+    /// are calls pending at the same time.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// Timer::once(Duration::from_millis(100), || {
+    ///     println!("100 ms passed");
+    /// });
+    /// ```
+    pub fn once<F>(delay: Duration, callback: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        let (id, key) = acquire_sim_timer();
+        let mut callback = Some(callback);
+        register_timer(
+            key,
+            Box::new(move || {
+                if let Some(callback) = callback.take() {
+                    callback();
+                }
+                // The trampoline drops this closure once it returns. Nothing runs in between, so
+                // the timer can't be reused before that.
+                unregister_timer(key);
+                release_sim_timer(id, key);
+            }),
+        );
+        unsafe {
+            timerStartNanos(id, delay.as_nanos() as f64, false);
+        }
+    }
+
+    /// Calls `callback` once, when the simulation time reaches `deadline` (see [`sim_time`]). If
+    /// the deadline has already passed, the callback is called as soon as possible, from a timer
+    /// rather than from inside this call.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// Timer::at(Duration::from_secs(1), || {
+    ///     println!("1 s into the simulation");
+    /// });
+    /// ```
+    pub fn at<F>(deadline: Duration, callback: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        Self::once(deadline.saturating_sub(sim_time()), callback);
+    }
+
+    /// Starts the timer, so it fires after `period`, and then every `period` if `repeat` is true.
+    /// Restarts the timer if it's already running.
+    pub fn start(&self, period: Duration, repeat: bool) {
+        self.start_nanos(period.as_nanos() as f64, repeat);
+    }
+
+    /// Like [`Timer::start`], with the period in nanoseconds.
+    pub fn start_nanos(&self, nanos: f64, repeat: bool) {
+        let nanos = if repeat {
+            nanos.max(MIN_PERIOD.as_nanos() as f64)
+        } else {
+            nanos
+        };
+        self.set_started(nanos, repeat);
+        unsafe {
+            timerStartNanos(self.id, nanos, repeat);
+        }
+    }
+
+    /// Like [`Timer::start`], with the period in whole microseconds, e.g. for bit-banged
+    /// protocols.
+    ///
+    /// Periods up to `u32::MAX` microseconds (about 71 minutes) go straight to the simulator's
+    /// microsecond timer. Longer periods are passed on in nanoseconds instead, which is exact up to
+    /// about 104 days. A zero period fires a one-shot timer right away (at the current simulation
+    /// time, after the chip code returns); a repeating timer is rounded up to [`MIN_PERIOD`].
+    ///
+    /// Example, a 100 µs low pulse:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// let out = Pin::new("OUT", PinMode::OutputLow);
+    /// let end_pulse = Timer::new(move || out.set_high());
+    /// end_pulse.start_micros(100, false);
+    /// ```
+    pub fn start_micros(&self, micros: u64, repeat: bool) {
+        match u32::try_from(micros) {
+            Ok(micros) if micros > 0 || !repeat => {
+                self.set_started(micros as f64 * 1000.0, repeat);
+                unsafe {
+                    timerStart(self.id, micros, repeat);
+                }
+            }
+            _ => self.start_nanos(micros as f64 * 1000.0, repeat),
+        }
+    }
+
+    /// Changes the period of the timer. A running repeating timer restarts with the new period,
+    /// so the next tick is `period` from now. Otherwise, the new period is used when the timer is
+    /// resumed.
+    pub fn set_period(&self, period: Duration) {
+        let nanos = period.as_nanos() as f64;
+        if self.is_running() && self.state.repeat.get() {
+            self.start_nanos(nanos, true);
+        } else {
+            self.state.period_nanos.set(nanos);
+        }
+    }
+
+    pub fn period(&self) -> Duration {
+        Duration::from_nanos(self.state.period_nanos.get() as u64)
+    }
+
+    /// Returns true if the timer is going to fire: it was started, and isn't stopped, paused, or
+    /// a one-shot timer that already fired.
+    pub fn is_running(&self) -> bool {
+        self.state.running.get()
+    }
+
+    /// Stops a running timer, keeping its period, so that [`Timer::resume`] can restart it.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use wokwi_chips_api::pin::{Pin, PinMode, PinValue, WatchEdge};
+    /// use wokwi_chips_api::timer::Timer;
+    ///
+    /// let led = Pin::new("LED", PinMode::OutputLow);
+    /// let blink = Timer::periodic(Duration::from_millis(250), move || led.toggle());
+    /// let enable = Pin::new("EN", PinMode::Input);
+    /// enable.watch(WatchEdge::Both, move |_, value| {
+    ///     if value == PinValue::High {
+    ///         blink.resume();
+    ///     } else {
+    ///         blink.pause();
+    ///     }
+    /// });
+    /// ```
+    pub fn pause(&self) {
+        if self.is_running() {
+            self.stop();
+            self.state.paused.set(true);
+        }
+    }
+
+    /// Restarts a timer stopped by [`Timer::pause`], with the same period and repeat mode. The
+    /// timer starts a full period from now: the part of the period that had passed before the
+    /// pause isn't remembered. Does nothing if the timer isn't paused.
+    pub fn resume(&self) {
+        if self.state.paused.get() {
+            self.start_nanos(self.state.period_nanos.get(), self.state.repeat.get());
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.get()
+    }
+
+    pub fn stop(&self) {
+        self.state.running.set(false);
+        self.state.paused.set(false);
+        unsafe {
+            timerStop(self.id);
+        }
+    }
+}
+
+impl Drop for Timer {
+    // The simulator can't free a timer, so it's stopped, the closure is removed from the
+    // registry, and the simulator timer goes back to the pool for the next timer.
+    fn drop(&mut self) {
+        self.stop();
+        unregister_timer(self.key);
+        release_sim_timer(self.id, self.key);
+    }
+}
+
+/// Returns a future that completes `duration` from now, for async chip code (see
+/// [`executor`](crate::executor)).
+///
+/// The timer starts when this is called, not when the future is first polled. Dropping the
+/// future stops the timer, and the next `sleep` reuses it, so a task sleeping in a loop doesn't
+/// create a simulator timer per iteration.
+///
+/// Example, a chip-select pulse:
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use wokwi_chips_api::pin::Pin;
+/// use wokwi_chips_api::timer::sleep;
+///
+/// async fn select(cs: &Pin) {
+///     cs.set_low();
+///     sleep(Duration::from_micros(10)).await;
+///     cs.set_high();
+/// }
+/// ```
+pub fn sleep(duration: Duration) -> Sleep {
+    let state = Rc::new(SleepState::default());
+    let timer_state = state.clone();
+    let timer = Timer::new(move || {
+        timer_state.done.set(true);
+        if let Some(waker) = timer_state.waker.take() {
+            waker.wake();
+        }
+    });
+    timer.start(duration, false);
+    Sleep {
+        state,
+        _timer: timer,
+    }
+}
+
+/// A future that completes after a delay, see [`sleep`].
+#[must_use = "futures do nothing unless awaited"]
+pub struct Sleep {
+    state: Rc<SleepState>,
+    // Dropping the future stops the timer.
+    _timer: Timer,
+}
+
+// Shared with the timer callback.
+#[derive(Default)]
+struct SleepState {
+    done: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.done.get() {
+            return Poll::Ready(());
+        }
+        let mut waker = self.state.waker.borrow_mut();
+        match waker.as_mut() {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn once_fires_exactly_once() {
+        let fired = Rc::new(Cell::new(0));
+        let timer_fired = fired.clone();
+        Timer::once(Duration::from_micros(10), move || {
+            timer_fired.set(timer_fired.get() + 1)
+        });
+        mock::advance(Duration::from_micros(9));
+        assert_eq!(fired.get(), 0);
+        mock::advance(Duration::from_micros(1));
+        assert_eq!(fired.get(), 1);
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(fired.get(), 1);
+    }
+
+    #[test]
+    fn once_reuses_finished_timers() {
+        let fired = Rc::new(Cell::new(0));
+        for _ in 0..100 {
+            let timer_fired = fired.clone();
+            Timer::once(Duration::from_micros(10), move || {
+                timer_fired.set(timer_fired.get() + 1)
+            });
+            mock::advance(Duration::from_micros(10));
+        }
+        assert_eq!(fired.get(), 100);
+        assert_eq!(mock::timer_count(), 1);
+
+        // Only the calls pending at the same time need timers of their own.
+        for round in 0..10 {
+            for delay in 1..=5 {
+                let timer_fired = fired.clone();
+                Timer::once(Duration::from_micros(delay), move || {
+                    timer_fired.set(timer_fired.get() + 1)
+                });
+            }
+            mock::advance(Duration::from_micros(5));
+            assert_eq!(fired.get(), 105 + round * 5);
+        }
+        assert_eq!(mock::timer_count(), 5);
+    }
+
+    #[test]
+    fn repeated_pulses_and_deadlines_reuse_one_timer() {
+        let strobe = crate::pin::Pin::new("STB", crate::pin::PinMode::OutputLow);
+        for _ in 0..50 {
+            strobe.pulse(crate::pin::PinValue::High, Duration::from_micros(1));
+            mock::advance(Duration::from_micros(2));
+            Timer::at(sim_time() + Duration::from_micros(1), || {});
+            mock::advance(Duration::from_micros(2));
+        }
+        assert_eq!(mock::pin_value("STB"), crate::pin::PinValue::Low);
+        assert_eq!(mock::timer_count(), 1);
+    }
+
+    #[test]
+    fn start_micros_fires_a_100us_one_shot() {
+        let fired = Rc::new(Cell::new(0));
+        let timer_fired = fired.clone();
+        let timer = Timer::new(move || timer_fired.set(timer_fired.get() + 1));
+        timer.start_micros(100, false);
+        assert_eq!(timer.period(), Duration::from_micros(100));
+        mock::advance(Duration::from_micros(99));
+        assert_eq!(fired.get(), 0);
+        assert!(timer.is_running());
+        mock::advance(Duration::from_micros(1));
+        assert_eq!(fired.get(), 1);
+        assert!(!timer.is_running());
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(fired.get(), 1);
+    }
+
+    #[test]
+    fn start_micros_rounds_a_zero_repeating_period_up() {
+        let fired = Rc::new(Cell::new(0));
+        let timer_fired = fired.clone();
+        let timer = Timer::new(move || timer_fired.set(timer_fired.get() + 1));
+        timer.start_micros(0, true);
+        assert_eq!(timer.period(), MIN_PERIOD);
+        mock::advance(Duration::from_nanos(10));
+        assert_eq!(fired.get(), 10);
+    }
+
+    #[test]
+    fn pause_and_resume_restart_a_full_period() {
+        let ticks = Rc::new(Cell::new(0));
+        let timer_ticks = ticks.clone();
+        let timer = Timer::periodic(Duration::from_micros(10), move || {
+            timer_ticks.set(timer_ticks.get() + 1)
+        });
+        mock::advance(Duration::from_micros(25));
+        assert_eq!(ticks.get(), 2);
+
+        timer.pause();
+        assert!(timer.is_paused());
+        assert!(!timer.is_running());
+        mock::advance(Duration::from_micros(100));
+        assert_eq!(ticks.get(), 2);
+
+        // The 5 µs left of the paused period are forgotten.
+        timer.resume();
+        assert!(!timer.is_paused());
+        mock::advance(Duration::from_micros(9));
+        assert_eq!(ticks.get(), 2);
+        mock::advance(Duration::from_micros(1));
+        assert_eq!(ticks.get(), 3);
+        mock::advance(Duration::from_micros(20));
+        assert_eq!(ticks.get(), 5);
+
+        // Resuming a timer that isn't paused does nothing.
+        timer.stop();
+        timer.resume();
+        mock::advance(Duration::from_micros(100));
+        assert_eq!(ticks.get(), 5);
+    }
+
+    #[test]
+    fn once_from_a_once_callback() {
+        let fired = Rc::new(Cell::new(0));
+        let timer_fired = fired.clone();
+        Timer::once(Duration::from_micros(10), move || {
+            timer_fired.set(1);
+            Timer::once(Duration::from_micros(10), move || timer_fired.set(2));
+        });
+        mock::advance(Duration::from_micros(10));
+        assert_eq!(fired.get(), 1);
+        mock::advance(Duration::from_micros(10));
+        assert_eq!(fired.get(), 2);
+        assert_eq!(mock::timer_count(), 2);
+    }
+
+    #[test]
+    fn dropped_timers_are_reused() {
+        for _ in 0..10 {
+            let timer = Timer::new(|| {});
+            timer.start(Duration::from_micros(1), true);
+        }
+        let _first = Timer::new(|| {});
+        let _second = Timer::new(|| {});
+        assert_eq!(mock::timer_count(), 2);
+    }
+
+    #[test]
+    fn a_sleep_loop_reuses_its_timer() {
+        let wakeups = Rc::new(Cell::new(0));
+        let task_wakeups = wakeups.clone();
+        crate::executor::spawn(async move {
+            // The task finishes, so that no sleep is left when the test thread exits.
+            for _ in 0..100 {
+                sleep(Duration::from_micros(10)).await;
+                task_wakeups.set(task_wakeups.get() + 1);
+            }
+        });
+        crate::executor::run();
+        mock::advance(Duration::from_millis(1));
+        assert_eq!(wakeups.get(), 100);
+        assert_eq!(mock::timer_count(), 1);
+    }
+}
@@ -0,0 +1,760 @@
+// SPDX-License-Identifier: MIT
+// Author: Uri Shaked
+
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::mem;
+use std::time::Duration;
+
+use wokwi_chip_ll::{uartInit, uartWrite, UARTConfig, UARTDevId};
+
+use crate::pin::Pin;
+use crate::timer::Timer;
+
+/// Like [`println!`](crate::println!), for writing to a [`Uart`] (or anything that dereferences
+/// to one, e.g. `Rc<Uart>`).
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::uart::Uart;
+/// use wokwi_chips_api::uart_println;
+///
+/// let uart = Uart::new(
+///     Pin::new("RX", PinMode::Input),
+///     Pin::new("TX", PinMode::InputPullup),
+///     115200,
+///     |_| {},
+/// );
+/// uart_println!(uart, "Temperature: {}°C", 25);
+///
+/// let shared = std::rc::Rc::new(uart);
+/// uart_println!(shared);
+/// ```
+#[macro_export]
+macro_rules! uart_println {
+  ($uart:expr) => {
+      $crate::uart_print!($uart, "\n")
+  };
+  ($uart:expr, $($arg:tt)*) => {
+      {
+          use core::fmt::Write;
+          let mut uart: &$crate::uart::Uart = &$uart;
+          writeln!(uart, $($arg)*).ok();
+      }
+  };
+}
+
+/// Like [`print!`](crate::print), for writing to a [`Uart`].
+#[macro_export]
+macro_rules! uart_print {
+  ($uart:expr, $($arg:tt)*) => {
+      {
+          use core::fmt::Write;
+          let mut uart: &$crate::uart::Uart = &$uart;
+          write!(uart, $($arg)*).ok();
+      }
+  };
+}
+
+/// Parity bit of a UART frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum StopBits {
+    #[default]
+    One,
+    Two,
+}
+
+/// UART line settings. The default is 115200 baud, 8N1 (8 data bits, no parity, one stop bit).
+///
+/// A baud rate converts into a config with the default frame format, so
+/// `Uart::new(rx, tx, 9600, callback)` works too.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UartConfig {
+    pub baud: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self {
+            baud: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+impl From<u32> for UartConfig {
+    fn from(baud: u32) -> Self {
+        Self {
+            baud,
+            ..Self::default()
+        }
+    }
+}
+
+impl UartConfig {
+    /// Checks that the simulator supports this config.
+    ///
+    /// The simulator's UART only transmits and receives 8N1 frames, so other frame formats are
+    /// rejected instead of silently talking 8N1 to the other side.
+    ///
+    /// ```rust
+    /// use wokwi_chips_api::uart::{Parity, UartConfig, UartConfigError};
+    ///
+    /// assert_eq!(UartConfig::default().validate(), Ok(()));
+    /// assert_eq!(UartConfig::from(9600).validate(), Ok(()));
+    ///
+    /// let even = UartConfig {
+    ///     parity: Parity::Even,
+    ///     ..UartConfig::default()
+    /// };
+    /// assert_eq!(even.validate(), Err(UartConfigError::UnsupportedFrameFormat));
+    ///
+    /// let seven_bits = UartConfig {
+    ///     data_bits: 7,
+    ///     ..UartConfig::default()
+    /// };
+    /// assert_eq!(seven_bits.validate(), Err(UartConfigError::UnsupportedFrameFormat));
+    ///
+    /// let twelve_bits = UartConfig {
+    ///     data_bits: 12,
+    ///     ..UartConfig::default()
+    /// };
+    /// assert_eq!(twelve_bits.validate(), Err(UartConfigError::InvalidDataBits(12)));
+    /// ```
+    pub fn validate(&self) -> Result<(), UartConfigError> {
+        if self.baud == 0 {
+            return Err(UartConfigError::InvalidBaudRate);
+        }
+        if !(5..=9).contains(&self.data_bits) {
+            return Err(UartConfigError::InvalidDataBits(self.data_bits));
+        }
+        if self.data_bits != 8 || self.parity != Parity::None || self.stop_bits != StopBits::One {
+            return Err(UartConfigError::UnsupportedFrameFormat);
+        }
+        Ok(())
+    }
+}
+
+/// Error returned for a [`UartConfig`] the simulator can't use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartConfigError {
+    /// The baud rate is zero.
+    InvalidBaudRate,
+    /// UART frames have 5 to 9 data bits.
+    InvalidDataBits(u8),
+    /// The simulator only supports 8 data bits, no parity, and one stop bit.
+    UnsupportedFrameFormat,
+}
+
+impl std::fmt::Display for UartConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UartConfigError::InvalidBaudRate => write!(f, "invalid UART baud rate: 0"),
+            UartConfigError::InvalidDataBits(bits) => {
+                write!(f, "invalid number of UART data bits: {}", bits)
+            }
+            UartConfigError::UnsupportedFrameFormat => {
+                write!(f, "unsupported UART frame format, only 8N1 is supported")
+            }
+        }
+    }
+}
+
+impl std::error::Error for UartConfigError {}
+
+type ReceiveCallback = Box<dyn FnMut(u8) + 'static>;
+
+// UART callbacks only receive the `user_data` pointer, so each UART gets a registry key that is
+// passed as `user_data` and used to look up its callbacks.
+type UartKey = usize;
+
+struct UartListener {
+    key: UartKey,
+    id: UARTDevId,
+    // Taken out while the callback runs, so that the callback is free to access the registry.
+    receive_callback: Option<ReceiveCallback>,
+    // The bytes being transmitted. The simulator may read them until it calls `write_done`, so
+    // the buffer isn't touched until then.
+    tx_buffer: Vec<u8>,
+    // Bytes waiting for the current transmission to finish.
+    tx_queue: Vec<u8>,
+    // The time it takes to transmit one frame, used to retry when the simulator is busy.
+    frame_time: Duration,
+    // Whether a retry timer is already running.
+    retrying: bool,
+}
+
+thread_local! {
+    // This is a global registry of all the UARTs, so that we can keep the Rust callbacks alive as
+    // long as the UART exists.
+    static UART_REGISTRY: RefCell<Vec<UartListener>> = const { RefCell::new(Vec::new()) };
+    static NEXT_UART_KEY: Cell<UartKey> = const { Cell::new(0) };
+}
+
+fn register_uart(receive_callback: ReceiveCallback, frame_time: Duration) -> UartKey {
+    let key = NEXT_UART_KEY.get();
+    NEXT_UART_KEY.set(key + 1);
+    UART_REGISTRY.with_borrow_mut(|registry| {
+        registry.push(UartListener {
+            key,
+            id: 0,
+            receive_callback: Some(receive_callback),
+            tx_buffer: Vec::new(),
+            tx_queue: Vec::new(),
+            frame_time,
+            retrying: false,
+        });
+    });
+    key
+}
+
+fn unregister_uart(key: UartKey) {
+    UART_REGISTRY.with_borrow_mut(|registry| {
+        if let Some(index) = registry.iter().position(|listener| listener.key == key) {
+            let listener = registry.remove(index);
+            // The simulator may still be reading the bytes being transmitted.
+            mem::forget(listener.tx_buffer);
+        }
+    });
+}
+
+fn with_listener<R>(key: UartKey, f: impl FnOnce(&mut UartListener) -> R) -> Option<R> {
+    UART_REGISTRY.with_borrow_mut(|registry| {
+        registry
+            .iter_mut()
+            .find(|listener| listener.key == key)
+            .map(f)
+    })
+}
+
+fn queue_bytes(key: UartKey, data: &[u8]) {
+    with_listener(key, |listener| listener.tx_queue.extend_from_slice(data));
+    start_transmit(key);
+}
+
+// Hands the queued bytes to the simulator, unless a transmission is already in progress.
+fn start_transmit(key: UartKey) {
+    let transmit = with_listener(key, |listener| {
+        if !listener.tx_buffer.is_empty() || listener.tx_queue.is_empty() {
+            return None;
+        }
+        mem::swap(&mut listener.tx_buffer, &mut listener.tx_queue);
+        let buffer = &listener.tx_buffer;
+        Some((listener.id, buffer.as_ptr(), buffer.len() as u32))
+    })
+    .flatten();
+    let Some((id, buffer, count)) = transmit else {
+        return;
+    };
+    // The registry isn't borrowed here, in case the simulator calls `write_done` right away.
+    if !unsafe { uartWrite(id, buffer, count) } {
+        // The simulator is busy, so put the bytes back in the queue, and try again after a frame
+        // time: the simulator doesn't call `write_done` for a write it rejected.
+        let retry = with_listener(key, |listener| {
+            let mut queue = mem::take(&mut listener.tx_buffer);
+            queue.append(&mut listener.tx_queue);
+            listener.tx_queue = queue;
+            (!mem::replace(&mut listener.retrying, true)).then_some(listener.frame_time)
+        })
+        .flatten();
+        if let Some(frame_time) = retry {
+            Timer::once(frame_time, move || {
+                with_listener(key, |listener| listener.retrying = false);
+                start_transmit(key);
+            });
+        }
+    }
+}
+
+fn take_receive_callback(key: UartKey) -> Option<ReceiveCallback> {
+    UART_REGISTRY.with_borrow_mut(|registry| {
+        registry
+            .iter_mut()
+            .find(|listener| listener.key == key)
+            .and_then(|listener| listener.receive_callback.take())
+    })
+}
+
+fn restore_receive_callback(key: UartKey, callback: ReceiveCallback) {
+    UART_REGISTRY.with_borrow_mut(|registry| {
+        // If the UART was dropped while the callback was running, or the callback replaced
+        // itself, the callback is dropped here.
+        if let Some(listener) = registry.iter_mut().find(|listener| listener.key == key) {
+            if listener.receive_callback.is_none() {
+                listener.receive_callback = Some(callback);
+            }
+        }
+    });
+}
+
+extern "C" fn uart_rx_trampoline(user_data: *mut c_void, byte: u8) {
+    let key = user_data as UartKey;
+    let Some(mut callback) = take_receive_callback(key) else {
+        return;
+    };
+    callback(byte);
+    restore_receive_callback(key, callback);
+}
+
+extern "C" fn uart_write_done_trampoline(user_data: *mut c_void) {
+    let key = user_data as UartKey;
+    with_listener(key, |listener| listener.tx_buffer.clear());
+    start_transmit(key);
+}
+
+/// A UART (serial port), for emulating serial peripherals.
+///
+/// The simulator can't remove a UART, so after the `Uart` is dropped, incoming bytes are ignored.
+///
+/// Example:
+///
+/// ```rust,no_run
+/// use wokwi_chips_api::pin::{Pin, PinMode};
+/// use wokwi_chips_api::uart::Uart;
+///
+/// let rx = Pin::new("RX", PinMode::Input);
+/// let tx = Pin::new("TX", PinMode::InputPullup);
+/// let uart = Uart::new(rx, tx, 115200, |byte| {
+///     println!("Received: 0x{:02x}", byte);
+/// });
+/// uart.write_bytes(b"READY\r\n");
+/// ```
+pub struct Uart {
+    id: UARTDevId,
+    key: UartKey,
+    config: UartConfig,
+    rx: Pin,
+    tx: Pin,
+}
+
+impl Uart {
+    /// Creates a UART receiving on `rx` and transmitting on `tx`, calling `receive_callback` for
+    /// every received byte. `config` is a [`UartConfig`], or just the baud rate for 8N1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the simulator doesn't support the config. Use [`Uart::try_new`] to handle the
+    /// error instead.
+    pub fn new<F>(rx: Pin, tx: Pin, config: impl Into<UartConfig>, receive_callback: F) -> Self
+    where
+        F: FnMut(u8) + 'static,
+    {
+        Self::try_new(rx, tx, config, receive_callback).unwrap()
+    }
+
+    /// Creates a UART, or returns an error if the simulator doesn't support the config.
+    pub fn try_new<F>(
+        rx: Pin,
+        tx: Pin,
+        config: impl Into<UartConfig>,
+        receive_callback: F,
+    ) -> Result<Self, UartConfigError>
+    where
+        F: FnMut(u8) + 'static,
+    {
+        let config = config.into();
+        config.validate()?;
+        // 8N1: a start bit, 8 data bits and a stop bit.
+        let frame_time = Duration::from_nanos(10_000_000_000 / config.baud as u64);
+        let key = register_uart(Box::new(receive_callback), frame_time);
+        let ll_config = UARTConfig {
+            user_data: key as *const c_void,
+            rx: rx.get_id(),
+            tx: tx.get_id(),
+            baud_rate: config.baud,
+            rx_data: uart_rx_trampoline as *const c_void,
+            write_done: uart_write_done_trampoline as *const c_void,
+        };
+        let id = unsafe { uartInit(&ll_config) };
+        with_listener(key, |listener| listener.id = id);
+
+        Ok(Self {
+            id,
+            key,
+            config,
+            rx,
+            tx,
+        })
+    }
+
+    /// Replaces the receive callback. The callback is called once for each received byte, in the
+    /// order the bytes arrive.
+    ///
+    /// The callback may write to the UART, or even replace itself: the UART registry isn't
+    /// borrowed while it runs.
+    ///
+    /// Example, an echo device:
+    ///
+    /// ```rust,no_run
+    /// use std::rc::Rc;
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::uart::Uart;
+    ///
+    /// let rx = Pin::new("RX", PinMode::Input);
+    /// let tx = Pin::new("TX", PinMode::InputPullup);
+    /// let uart = Rc::new(Uart::new(rx, tx, 9600, |_| {}));
+    /// // A weak reference, so the callback doesn't keep the UART alive.
+    /// let echo = Rc::downgrade(&uart);
+    /// uart.on_receive(move |byte| {
+    ///     if let Some(uart) = echo.upgrade() {
+    ///         uart.write(byte);
+    ///     }
+    /// });
+    /// ```
+    pub fn on_receive<F>(&self, callback: F)
+    where
+        F: FnMut(u8) + 'static,
+    {
+        with_listener(self.key, |listener| {
+            listener.receive_callback = Some(Box::new(callback));
+        });
+    }
+
+    /// Transmits a single byte. See [`Uart::write_bytes`].
+    pub fn write(&self, byte: u8) {
+        self.write_bytes(&[byte]);
+    }
+
+    /// Transmits `data`.
+    ///
+    /// The bytes are copied to a transmit queue, so this never blocks or drops data, and `data`
+    /// can be reused right away. The simulator transmits a buffer at a time, at the configured
+    /// baud rate: bytes written while a transmission is in progress are queued, and sent together
+    /// when it completes. If the simulator rejects a write, it's retried after a frame time. The
+    /// queue isn't bounded, so a chip that writes faster than the baud rate allows keeps growing
+    /// it; use [`Uart::pending`] to throttle.
+    pub fn write_bytes(&self, data: &[u8]) {
+        queue_bytes(self.key, data);
+    }
+
+    /// Returns the number of bytes that were written but haven't been transmitted yet.
+    pub fn pending(&self) -> usize {
+        with_listener(self.key, |listener| {
+            listener.tx_buffer.len() + listener.tx_queue.len()
+        })
+        .unwrap_or(0)
+    }
+
+    pub fn config(&self) -> UartConfig {
+        self.config
+    }
+
+    pub fn rx(&self) -> &Pin {
+        &self.rx
+    }
+
+    pub fn tx(&self) -> &Pin {
+        &self.tx
+    }
+
+    pub fn get_id(&self) -> UARTDevId {
+        self.id
+    }
+}
+
+impl Drop for Uart {
+    fn drop(&mut self) {
+        unregister_uart(self.key);
+    }
+}
+
+/// Writes formatted text to the UART as UTF-8, e.g. with `write!` or [`uart_println!`].
+impl core::fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        (&*self).write_str(s)
+    }
+}
+
+impl core::fmt::Write for &Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+type LineCallback = Box<dyn FnMut(&str) + 'static>;
+
+/// Error returned by [`LineReader::push`] when a line is longer than the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineOverflow {
+    pub max_len: usize,
+}
+
+impl std::fmt::Display for LineOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line longer than {} bytes, truncated", self.max_len)
+    }
+}
+
+impl std::error::Error for LineOverflow {}
+
+/// Collects received bytes into lines, for command-style protocols such as AT commands.
+///
+/// Each line is passed to the callback without the delimiter (a newline by default). With the
+/// default delimiter, a `\r` before the newline is removed too, so `\r\n` line endings work.
+/// Bytes that aren't valid UTF-8 are replaced with U+FFFD.
+///
+/// ```rust
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use wokwi_chips_api::uart::LineReader;
+///
+/// let lines = Rc::new(RefCell::new(Vec::new()));
+/// let reader_lines = lines.clone();
+/// let mut reader = LineReader::new(move |line| reader_lines.borrow_mut().push(line.to_string()));
+/// for byte in b"AT\r\nOK\r\n" {
+///     reader.push(*byte).unwrap();
+/// }
+/// assert_eq!(*lines.borrow(), ["AT", "OK"]);
+/// ```
+///
+/// Lines longer than the buffer are truncated:
+///
+/// ```rust
+/// use wokwi_chips_api::uart::{LineOverflow, LineReader};
+///
+/// let mut reader = LineReader::new(|line| assert_eq!(line, "ABCD")).max_len(4);
+/// let results: Vec<_> = b"ABCDEF\n".iter().map(|byte| reader.push(*byte)).collect();
+/// assert_eq!(results[3], Ok(()));
+/// assert_eq!(results[4], Err(LineOverflow { max_len: 4 }));
+/// ```
+pub struct LineReader {
+    buffer: Vec<u8>,
+    delimiter: u8,
+    max_len: usize,
+    callback: LineCallback,
+}
+
+impl LineReader {
+    /// Creates a reader for newline-terminated lines of up to 256 bytes.
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: FnMut(&str) + 'static,
+    {
+        Self {
+            buffer: Vec::new(),
+            delimiter: b'\n',
+            max_len: 256,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Sets the byte that ends a line.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the maximum line length, not including the delimiter.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Adds a received byte, calling the callback if it completes a line. Returns an error if
+    /// the line is already `max_len` bytes long, in which case the byte is dropped. The truncated
+    /// line is still passed to the callback when the delimiter arrives.
+    pub fn push(&mut self, byte: u8) -> Result<(), LineOverflow> {
+        if byte == self.delimiter {
+            let mut line = &self.buffer[..];
+            if self.delimiter == b'\n' {
+                line = line.strip_suffix(b"\r").unwrap_or(line);
+            }
+            (self.callback)(&String::from_utf8_lossy(line));
+            self.buffer.clear();
+            return Ok(());
+        }
+        if self.buffer.len() >= self.max_len {
+            return Err(LineOverflow {
+                max_len: self.max_len,
+            });
+        }
+        self.buffer.push(byte);
+        Ok(())
+    }
+
+    /// Returns a UART receive callback feeding this reader. Overflows are reported on the debug
+    /// console, once per line.
+    ///
+    /// Example:
+    ///
+    /// ```rust,no_run
+    /// use wokwi_chips_api::pin::{Pin, PinMode};
+    /// use wokwi_chips_api::uart::{LineReader, Uart};
+    ///
+    /// let rx = Pin::new("RX", PinMode::Input);
+    /// let tx = Pin::new("TX", PinMode::InputPullup);
+    /// let reader = LineReader::new(|line| println!("Command: {}", line));
+    /// let uart = Uart::new(rx, tx, 9600, reader.into_receive_callback());
+    /// ```
+    pub fn into_receive_callback(mut self) -> impl FnMut(u8) + 'static {
+        let mut overflowed = false;
+        move |byte| match self.push(byte) {
+            Ok(()) => {
+                if byte == self.delimiter {
+                    overflowed = false;
+                }
+            }
+            Err(err) => {
+                if !overflowed {
+                    crate::println!("warning: UART {}", err);
+                    overflowed = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use crate::pin::PinMode;
+    use std::rc::Rc;
+
+    fn uart(receive_callback: impl FnMut(u8) + 'static) -> Uart {
+        let rx = Pin::new("RX", PinMode::Input);
+        let tx = Pin::new("TX", PinMode::InputPullup);
+        Uart::new(rx, tx, 115200, receive_callback)
+    }
+
+    #[test]
+    fn written_bytes_loop_back_to_the_receive_callback() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let uart_received = received.clone();
+        let uart = uart(move |byte| uart_received.borrow_mut().push(byte));
+        uart.write(b'>');
+        uart.write_bytes(b"HELLO");
+        mock::complete_uart_writes();
+
+        let output = mock::take_uart_output(uart.get_id());
+        assert_eq!(output, b">HELLO");
+        mock::uart_receive(uart.get_id(), &output);
+        assert_eq!(*received.borrow(), b">HELLO");
+        mock::complete_uart_writes();
+        assert_eq!(uart.pending(), 0);
+    }
+
+    #[test]
+    fn bytes_written_during_a_transmission_are_queued() {
+        let uart = uart(|_| {});
+        uart.write_bytes(b"AB");
+        uart.write_bytes(b"CD");
+        uart.write(b'E');
+        assert_eq!(mock::take_uart_output(uart.get_id()), b"AB");
+        assert_eq!(uart.pending(), 5);
+
+        mock::complete_uart_writes();
+        assert_eq!(mock::take_uart_output(uart.get_id()), b"CDE");
+        assert_eq!(uart.pending(), 3);
+
+        mock::complete_uart_writes();
+        assert_eq!(uart.pending(), 0);
+        assert!(mock::take_uart_output(uart.get_id()).is_empty());
+    }
+
+    #[test]
+    fn a_rejected_write_is_retried_after_a_frame_time() {
+        let uart = uart(|_| {});
+        mock::set_uart_busy(uart.get_id(), true);
+        uart.write_bytes(b"OK");
+        assert!(mock::take_uart_output(uart.get_id()).is_empty());
+        assert_eq!(uart.pending(), 2);
+
+        // Still busy at the first retry, so the bytes stay queued.
+        mock::advance(Duration::from_micros(100));
+        assert!(mock::take_uart_output(uart.get_id()).is_empty());
+
+        mock::set_uart_busy(uart.get_id(), false);
+        mock::advance(Duration::from_micros(100));
+        assert_eq!(mock::take_uart_output(uart.get_id()), b"OK");
+        mock::complete_uart_writes();
+        assert_eq!(uart.pending(), 0);
+    }
+
+    #[test]
+    fn uart_println_writes_formatted_text() {
+        let uart = Rc::new(uart(|_| {}));
+        uart_print!(uart, "T={}", 25);
+        uart_println!(uart, "°C");
+        uart_println!(uart);
+        while uart.pending() > 0 {
+            mock::complete_uart_writes();
+        }
+        assert_eq!(
+            mock::take_uart_output(uart.get_id()),
+            "T=25°C\n\n".as_bytes()
+        );
+    }
+
+    fn line_log() -> (Rc<RefCell<Vec<String>>>, impl FnMut(&str) + 'static) {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let callback_lines = lines.clone();
+        (lines, move |line: &str| {
+            callback_lines.borrow_mut().push(line.to_string())
+        })
+    }
+
+    #[test]
+    fn line_reader_splits_crlf_lines() {
+        let (lines, callback) = line_log();
+        let mut reader = LineReader::new(callback);
+        for &byte in b"AT\r\nOK\r\n" {
+            assert_eq!(reader.push(byte), Ok(()));
+        }
+        assert_eq!(*lines.borrow(), ["AT", "OK"]);
+    }
+
+    #[test]
+    fn line_reader_keeps_carriage_returns_with_a_custom_delimiter() {
+        let (lines, callback) = line_log();
+        let mut reader = LineReader::new(callback).delimiter(b';');
+        for &byte in b"A\r;B\n;" {
+            reader.push(byte).unwrap();
+        }
+        assert_eq!(*lines.borrow(), ["A\r", "B\n"]);
+    }
+
+    #[test]
+    fn line_reader_truncates_long_lines() {
+        let (lines, callback) = line_log();
+        let mut reader = LineReader::new(callback).max_len(3);
+        let results: Vec<_> = b"ABCDE\nXY\n"
+            .iter()
+            .map(|&byte| reader.push(byte))
+            .collect();
+        let overflow = Err(LineOverflow { max_len: 3 });
+        assert_eq!(results[..3], [Ok(()); 3]);
+        assert_eq!(results[3..5], [overflow; 2]);
+        assert!(results[5..].iter().all(Result::is_ok));
+        assert_eq!(*lines.borrow(), ["ABC", "XY"]);
+    }
+
+    #[test]
+    fn receive_callback_reports_an_overflow_once_per_line() {
+        let (lines, callback) = line_log();
+        let reader = LineReader::new(callback).max_len(2);
+        let uart = uart(reader.into_receive_callback());
+        mock::uart_receive(uart.get_id(), b"ABCD\nEFG\nOK\n");
+        assert_eq!(*lines.borrow(), ["AB", "EF", "OK"]);
+        assert_eq!(
+            mock::take_output(),
+            "warning: UART line longer than 2 bytes, truncated\n".repeat(2)
+        );
+    }
+}